@@ -0,0 +1,224 @@
+use std::{
+    collections::BTreeSet,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::scan::Album;
+
+/// operation run on every album of a library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// read tags and save them as a metadata file in the album directory
+    Export,
+    /// fetch metadata again by the ID stored in the metadata file and write it to files
+    Refresh,
+    /// embed the cover image found in the album directory
+    Cover,
+}
+
+impl FromStr for Action {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Action> {
+        match s.to_lowercase().as_str() {
+            "export" | "read" => Ok(Action::Export),
+            "refresh" | "fetch" => Ok(Action::Refresh),
+            "cover" | "picture" => Ok(Action::Cover),
+            _ => anyhow::bail!("Error: unknown action: {}", s),
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::Export => write!(f, "export"),
+            Action::Refresh => write!(f, "refresh"),
+            Action::Cover => write!(f, "cover"),
+        }
+    }
+}
+
+/// result of an action on one album.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Done,
+    /// nothing to do, with reason
+    Skipped(String),
+}
+
+/// albums already processed, saved after every album so an interrupted run can be resumed.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct State {
+    #[serde(skip)]
+    path: PathBuf,
+
+    pub action: String,
+    pub root: PathBuf,
+    pub done: BTreeSet<PathBuf>,
+}
+
+impl State {
+    /// load state of a previous run from `path`, or start a new one if it does not exist.
+    pub fn open<P: AsRef<Path>>(path: P, action: Action, root: &Path) -> anyhow::Result<State> {
+        let path = path.as_ref();
+
+        if !path.exists() {
+            return Ok(State {
+                path: path.into(),
+                action: action.to_string(),
+                root: root.into(),
+                done: BTreeSet::new(),
+            });
+        }
+
+        let mut state: State = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        if state.action != action.to_string() || state.root != root {
+            anyhow::bail!(
+                "Error: state file {} belongs to `{}` of {}",
+                path.display(),
+                state.action,
+                state.root.display()
+            )
+        }
+        state.path = path.into();
+        Ok(state)
+    }
+
+    pub fn is_done(&self, dir: &Path) -> bool {
+        self.done.contains(dir)
+    }
+
+    pub fn mark_done(&mut self, dir: &Path) -> anyhow::Result<()> {
+        self.done.insert(dir.into());
+
+        // write to a temporary file first, so an interrupt never leaves a broken state file
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+/// summary of a batch run.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub done: usize,
+    /// albums done in a previous run
+    pub resumed: usize,
+    pub skipped: Vec<(PathBuf, String)>,
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "done: {}, skipped: {}, failed: {}, done in previous run: {}",
+            self.done,
+            self.skipped.len(),
+            self.failed.len(),
+            self.resumed
+        )?;
+
+        if !self.skipped.is_empty() {
+            writeln!(f, "skipped:")?;
+        }
+        for (dir, reason) in &self.skipped {
+            writeln!(f, "  {}: {}", dir.display(), reason)?;
+        }
+
+        if !self.failed.is_empty() {
+            writeln!(f, "failed:")?;
+        }
+        for (dir, error) in &self.failed {
+            writeln!(f, "  {}: {}", dir.display(), error)?;
+        }
+        Ok(())
+    }
+}
+
+/// run `action` on every album with `workers` threads.
+/// albums recorded in `state` are not processed again, and albums done or skipped are added to it.
+pub fn run<F>(albums: &[Album], workers: usize, state: Option<State>, action: F) -> Report
+where
+    F: Fn(&Album) -> anyhow::Result<Outcome> + Sync,
+{
+    let mut report = Report::default();
+    let pending: Vec<_> = albums
+        .iter()
+        .filter(|a| match &state {
+            Some(s) if s.is_done(&a.dir) => {
+                report.resumed += 1;
+                false
+            }
+            _ => true,
+        })
+        .collect();
+
+    let total = pending.len();
+    let next = AtomicUsize::new(0);
+    let shared = Mutex::new((report, state));
+
+    std::thread::scope(|s| {
+        for _ in 0..workers.max(1).min(total.max(1)) {
+            s.spawn(|| loop {
+                let idx = next.fetch_add(1, Ordering::SeqCst);
+                let album = match pending.get(idx) {
+                    Some(album) => album,
+                    None => break,
+                };
+
+                let result = action(album);
+                let mut guard = shared.lock().unwrap();
+                let (report, state) = &mut *guard;
+
+                let finished = match result {
+                    Ok(Outcome::Done) => {
+                        println!("[{}/{}] done: {}", idx + 1, total, album.dir.display());
+                        report.done += 1;
+                        true
+                    }
+                    Ok(Outcome::Skipped(reason)) => {
+                        println!(
+                            "[{}/{}] skipped: {}: {}",
+                            idx + 1,
+                            total,
+                            album.dir.display(),
+                            reason
+                        );
+                        report.skipped.push((album.dir.clone(), reason));
+                        true
+                    }
+                    Err(e) => {
+                        println!(
+                            "[{}/{}] failed: {}: {}",
+                            idx + 1,
+                            total,
+                            album.dir.display(),
+                            e
+                        );
+                        report.failed.push((album.dir.clone(), e.to_string()));
+                        false
+                    }
+                };
+
+                if let (true, Some(state)) = (finished, state.as_mut()) {
+                    if let Err(e) = state.mark_done(&album.dir) {
+                        println!("Warning: could not save state: {}", e);
+                    }
+                }
+            });
+        }
+    });
+
+    shared.into_inner().unwrap().0
+}
@@ -0,0 +1,660 @@
+use std::{path::Path, str::FromStr};
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::{
+    info_struct::{Metadata, Track},
+    net::{Provider, ResourceId},
+    normalize::Normalization,
+    patch::{MetaPatch, Patch, TrackPatch},
+    sort_name,
+};
+
+/// words kept lowercase by title case, unless first or last
+const SMALL_WORDS: [&str; 18] = [
+    "a", "an", "and", "as", "at", "but", "by", "for", "from", "in", "into", "nor", "of", "on",
+    "or", "the", "to", "vs.",
+];
+
+/// featuring marks, with the character before them.
+/// `feat` and `ft` without a dot only right after a bracket, to leave words like "Feat" alone
+const FEAT_PATTERN: &str = r"(?i)(^|[\s(\[])(?:featuring|feat\.|ft\.)\s+|([(\[])(?:feat|ft)\s+";
+
+/// featured artists in a title, as `(feat. X)`, `[feat. X]` or `feat. X` at the end
+const FEATURED_PATTERN: &str = r"(?i)\s*(?:[(\[](?:featuring|feat\.?|ft\.?)\s+([^)\]]+)[)\]]|\s(?:featuring|feat\.|ft\.)\s+(.+)$)";
+
+/// `- Remastered 2011`, `(2011 Remaster)` and the like added to titles by Spotify
+const REMASTER_PATTERN: &str = r"(?i)\s*(?:-\s*|[(\[])(?:\d{4}\s+)?(?:digital\s+)?remaster(?:ed)?(?:\s+(?:version|\d{4}))*[)\]]?$";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TextField {
+    Album,
+    Genre,
+    Title,
+    Artist,
+}
+
+impl TextField {
+    const ALL: [TextField; 4] = [
+        TextField::Album,
+        TextField::Genre,
+        TextField::Title,
+        TextField::Artist,
+    ];
+
+    fn is_track_level(&self) -> bool {
+        matches!(self, TextField::Title | TextField::Artist)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum ActionConfig {
+    Replace {
+        pattern: String,
+        #[serde(default)]
+        replace: String,
+    },
+    TitleCase,
+    NormalizeFeat,
+    MoveFeatured,
+    Normalize {
+        #[serde(flatten)]
+        options: Normalization,
+    },
+    SortNames,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ConditionConfig {
+    field: TextField,
+    matches: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RuleConfig {
+    #[serde(flatten)]
+    action: ActionConfig,
+    #[serde(default)]
+    fields: Vec<TextField>,
+    #[serde(default)]
+    providers: Vec<String>,
+    when: Option<ConditionConfig>,
+}
+
+#[derive(Debug, Clone)]
+enum Action {
+    /// replace matches of regex, `$1` in the replacement refers to groups
+    Replace(Regex, String),
+    /// Title Case for values in Latin script
+    TitleCase,
+    /// `ft.`, `Feat` and `featuring` to `feat.`
+    NormalizeFeat(Regex),
+    /// featured artists in title to artist
+    MoveFeatured(Regex),
+    /// Unicode form, width and punctuation
+    Normalize(Normalization),
+    /// generate missing sort names
+    SortNames,
+}
+
+/// rule applied only if `field` matches `pattern`.
+/// a condition on a track field holds for album fields if it holds for every track.
+#[derive(Debug, Clone)]
+struct Condition {
+    field: TextField,
+    pattern: Regex,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    action: Action,
+    fields: Vec<TextField>,
+    /// providers whose metadata the rule applies to, None for local metadata. all if empty
+    providers: Vec<Option<Provider>>,
+    when: Option<Condition>,
+}
+
+/// rewrite rules applied to metadata after fetch or before write.
+///
+/// rules are read from a JSON array like
+/// `[{"action": "replace", "fields": ["title"], "pattern": "\\s*\\(Live\\)$", "replace": "",
+/// "providers": ["spotify"], "when": {"field": "album", "matches": "Live"}}]`,
+/// with actions `replace`, `title_case`, `normalize_feat`, `move_featured`, `sort_names` and
+/// `normalize`, which takes the fields of [`Normalization`].
+/// `providers` are `musicbrainz`, `spotify` or `local` for metadata not fetched from a provider.
+#[derive(Debug, Clone)]
+pub struct Cleaner {
+    rules: Vec<Rule>,
+    /// provider of metadata without ID, like tags
+    provider: Option<Provider>,
+}
+
+fn compile(pattern: &str) -> anyhow::Result<Regex> {
+    Regex::new(pattern)
+        .map_err(|e| anyhow::anyhow!("Error: invalid pattern in cleanup rule: {}", e))
+}
+
+impl Rule {
+    fn from_config(config: RuleConfig) -> anyhow::Result<Rule> {
+        let (action, default_fields) = match config.action {
+            ActionConfig::Replace { pattern, replace } => (
+                Action::Replace(compile(&pattern)?, replace),
+                TextField::ALL.to_vec(),
+            ),
+            ActionConfig::TitleCase => {
+                (Action::TitleCase, vec![TextField::Album, TextField::Title])
+            }
+            ActionConfig::NormalizeFeat => (
+                Action::NormalizeFeat(compile(FEAT_PATTERN)?),
+                vec![TextField::Title, TextField::Artist],
+            ),
+            ActionConfig::MoveFeatured => (
+                Action::MoveFeatured(compile(FEATURED_PATTERN)?),
+                vec![TextField::Title],
+            ),
+            ActionConfig::Normalize { options } => {
+                (Action::Normalize(options), TextField::ALL.to_vec())
+            }
+            ActionConfig::SortNames => (
+                Action::SortNames,
+                vec![TextField::Album, TextField::Title, TextField::Artist],
+            ),
+        };
+
+        let providers = config
+            .providers
+            .iter()
+            .map(|p| match p.as_str() {
+                "local" => Ok(None),
+                p => Provider::from_str(p).map(Some),
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        let when = match config.when {
+            Some(c) => Some(Condition {
+                field: c.field,
+                pattern: compile(&c.matches)?,
+            }),
+            None => None,
+        };
+
+        Ok(Rule {
+            action,
+            fields: if config.fields.is_empty() {
+                default_fields
+            } else {
+                config.fields
+            },
+            providers,
+            when,
+        })
+    }
+
+    /// true if the condition holds for `track`, or for the album if None.
+    fn holds(&self, meta: &Metadata, track: Option<&Track>) -> bool {
+        let cond = match &self.when {
+            Some(c) => c,
+            None => return true,
+        };
+        let track_value = |t: &Track| match cond.field {
+            TextField::Title => cond.pattern.is_match(&t.title),
+            _ => cond.pattern.is_match(&t.artist),
+        };
+
+        match (cond.field, track) {
+            (TextField::Album, _) => cond.pattern.is_match(&meta.album),
+            (TextField::Genre, _) => cond.pattern.is_match(&meta.genre),
+            (_, Some(track)) => track_value(track),
+            (_, None) => meta.tracks.iter().all(track_value),
+        }
+    }
+
+    fn rewrite(&self, value: &str) -> String {
+        match &self.action {
+            // trimmed only if changed, to leave spaces of other values alone
+            Action::Replace(re, replace) if re.is_match(value) => {
+                re.replace_all(value, replace.as_str()).trim().into()
+            }
+            Action::Replace(..) => value.into(),
+            Action::TitleCase => title_case(value),
+            Action::NormalizeFeat(re) => re.replace_all(value, "${1}${2}feat. ").into(),
+            Action::MoveFeatured(_) | Action::SortNames => value.into(),
+            Action::Normalize(options) => options.apply(value),
+        }
+    }
+
+    /// sort names of `fields` generated where missing.
+    fn fill_sort_names(&self, meta: &mut Metadata) {
+        if self.fields.contains(&TextField::Album)
+            && meta.album_sort.is_none()
+            && self.holds(meta, None)
+        {
+            meta.album_sort = sort_name::title_sort(&meta.album);
+        }
+
+        for i in 0..meta.tracks.len() {
+            if !self.holds(meta, Some(&meta.tracks[i])) {
+                continue;
+            }
+            let track = &mut meta.tracks[i];
+
+            if self.fields.contains(&TextField::Title) && track.title_sort.is_none() {
+                track.title_sort = sort_name::title_sort(&track.title);
+            }
+            if self.fields.contains(&TextField::Artist) && track.artist_sort.is_none() {
+                track.artist_sort = sort_name::artist_sort(&track.artist);
+            }
+        }
+    }
+
+    fn apply(&self, meta: &mut Metadata) {
+        if let Action::SortNames = self.action {
+            self.fill_sort_names(meta);
+            return;
+        }
+
+        if self.holds(meta, None) {
+            if self.fields.contains(&TextField::Album) {
+                meta.album = self.rewrite(&meta.album);
+            }
+            if self.fields.contains(&TextField::Genre) {
+                meta.genre = self.rewrite(&meta.genre);
+            }
+        }
+        if !self.fields.iter().any(TextField::is_track_level) {
+            return;
+        }
+
+        for i in 0..meta.tracks.len() {
+            if !self.holds(meta, Some(&meta.tracks[i])) {
+                continue;
+            }
+            let track = &mut meta.tracks[i];
+
+            if let Action::MoveFeatured(re) = &self.action {
+                move_featured(re, track);
+                continue;
+            }
+            if self.fields.contains(&TextField::Title) {
+                track.title = self.rewrite(&track.title);
+            }
+            if self.fields.contains(&TextField::Artist) {
+                track.artist = self.rewrite(&track.artist);
+            }
+        }
+    }
+}
+
+/// true if letters of `value` are all in Latin script.
+fn is_latin(value: &str) -> bool {
+    value
+        .chars()
+        .filter(|c| c.is_alphabetic())
+        .all(|c| c <= '\u{024F}')
+}
+
+fn capitalize(word: &str) -> String {
+    let mut done = false;
+    word.chars()
+        .map(|c| {
+            if !done && c.is_alphabetic() {
+                done = true;
+                c.to_uppercase().to_string()
+            } else {
+                c.to_string()
+            }
+        })
+        .collect()
+}
+
+/// Title Case: capitalize lowercase words except small words in the middle.
+/// words with capitals, like `iPhone` or `AC/DC`, are kept.
+fn title_case(value: &str) -> String {
+    if !is_latin(value) {
+        return value.into();
+    }
+
+    let words: Vec<&str> = value.split(' ').collect();
+    let mut result = Vec::with_capacity(words.len());
+    for (i, word) in words.iter().enumerate() {
+        let first =
+            i == 0 || word.starts_with(['(', '[', '"']) || words[i - 1].ends_with([':', '-', '(']);
+        let last = i + 1 == words.len() || word.ends_with([')', ']']);
+        let lower = word.to_lowercase();
+
+        if !first && !last && SMALL_WORDS.contains(&lower.as_str()) {
+            // `Of` to `of`, but keep `OF` or `oF`
+            if *word == capitalize(&lower) {
+                result.push(lower);
+            } else {
+                result.push(word.to_string());
+            }
+        } else if *word == lower {
+            result.push(capitalize(word));
+        } else {
+            result.push(word.to_string());
+        }
+    }
+    result.join(" ")
+}
+
+/// move `(feat. X)` in title of `track` to its artist.
+fn move_featured(re: &Regex, track: &mut Track) {
+    let caps = match re.captures(&track.title) {
+        Some(caps) => caps,
+        None => return,
+    };
+    let featured = match caps.get(1).or_else(|| caps.get(2)) {
+        Some(m) => m.as_str().trim().to_string(),
+        None => return,
+    };
+    let range = caps.get(0).unwrap().range();
+
+    let mut title = track.title.clone();
+    title.replace_range(range, "");
+    track.title = title.trim().into();
+
+    if track.artist.is_empty() {
+        track.artist = featured;
+    } else if !track
+        .artist
+        .to_lowercase()
+        .contains(&featured.to_lowercase())
+    {
+        track.artist = format!("{} feat. {}", track.artist, featured);
+    }
+}
+
+impl Default for Cleaner {
+    /// featuring marks normalized and moved to artists, remaster suffixes of Spotify removed.
+    fn default() -> Cleaner {
+        let rules = vec![
+            serde_json::json!({"action": "normalize_feat"}),
+            serde_json::json!({"action": "move_featured"}),
+            serde_json::json!({
+                "action": "replace",
+                "fields": ["title"],
+                "pattern": REMASTER_PATTERN,
+                "providers": ["spotify"],
+            }),
+        ];
+        Cleaner {
+            rules: rules
+                .into_iter()
+                .map(|r| Rule::from_config(serde_json::from_value(r).unwrap()).unwrap())
+                .collect(),
+            provider: None,
+        }
+    }
+}
+
+impl Cleaner {
+    /// rules from JSON file at `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Cleaner> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Error: could not read {}: {}", path.display(), e))?;
+        let configs: Vec<RuleConfig> = serde_json::from_str(&text).map_err(|e| {
+            anyhow::anyhow!("Error: invalid cleanup rules in {}: {}", path.display(), e)
+        })?;
+
+        Ok(Cleaner {
+            rules: configs
+                .into_iter()
+                .map(Rule::from_config)
+                .collect::<anyhow::Result<_>>()?,
+            provider: None,
+        })
+    }
+
+    /// cleaner with only `normalization` on all text fields.
+    pub fn normalizing(normalization: Normalization) -> Cleaner {
+        let mut result = Cleaner {
+            rules: Vec::new(),
+            provider: None,
+        };
+        result.normalize_first(normalization);
+        result
+    }
+
+    /// run `normalization` on all text fields before other rules.
+    pub fn normalize_first(&mut self, normalization: Normalization) {
+        let rule = Rule {
+            action: Action::Normalize(normalization),
+            fields: TextField::ALL.to_vec(),
+            providers: Vec::new(),
+            when: None,
+        };
+        self.rules.insert(0, rule);
+    }
+
+    /// treat metadata without ID as fetched from `provider`.
+    /// tags have no ID, so rules limited to a provider apply to them only with this.
+    pub fn assume_provider(&mut self, provider: Provider) {
+        self.provider = Some(provider);
+    }
+
+    /// apply rules for the provider `meta` was fetched from, found by its ID.
+    pub fn clean(&self, meta: &mut Metadata) {
+        let provider = match meta.id.as_deref() {
+            Some(id) => ResourceId::from_str(id).ok().map(|res| res.provider),
+            None => self.provider,
+        };
+
+        for rule in &self.rules {
+            if rule.providers.is_empty() || rule.providers.contains(&provider) {
+                rule.apply(meta);
+            }
+        }
+    }
+
+    /// `patch` with values changed by rules when applied to `current`.
+    pub fn clean_patch(&self, patch: &MetaPatch, current: &Metadata) -> MetaPatch {
+        let before = patch.apply(current);
+        let mut after = before.clone();
+        self.clean(&mut after);
+
+        let mut result = patch.clone();
+        let changed = |old: &String, new: &String| (old != new).then(|| Patch::Set(new.clone()));
+        let sort_changed = |old: &Option<String>, new: &Option<String>| match new {
+            Some(new) if old.as_ref() != Some(new) => Some(Patch::Set(new.clone())),
+            _ => None,
+        };
+
+        if let Some(p) = changed(&before.album, &after.album) {
+            result.album = p;
+        }
+        if let Some(p) = sort_changed(&before.album_sort, &after.album_sort) {
+            result.album_sort = p;
+        }
+        if let Some(p) = changed(&before.genre, &after.genre) {
+            result.genre = p;
+        }
+        for (i, (old, new)) in before.tracks.iter().zip(&after.tracks).enumerate() {
+            let title = changed(&old.title, &new.title);
+            let artist = changed(&old.artist, &new.artist);
+            let title_sort = sort_changed(&old.title_sort, &new.title_sort);
+            let artist_sort = sort_changed(&old.artist_sort, &new.artist_sort);
+            if title.is_none() && artist.is_none() && title_sort.is_none() && artist_sort.is_none()
+            {
+                continue;
+            }
+
+            if result.tracks.len() <= i {
+                result.tracks.resize(i + 1, TrackPatch::default());
+            }
+            if let Some(p) = title {
+                result.tracks[i].title = p;
+            }
+            if let Some(p) = artist {
+                result.tracks[i].artist = p;
+            }
+            if let Some(p) = title_sort {
+                result.tracks[i].title_sort = p;
+            }
+            if let Some(p) = artist_sort {
+                result.tracks[i].artist_sort = p;
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPOTIFY_ID: &str = "spotify:album:4aawyAB9vmqN3uQ7FjRGTy";
+
+    fn rules(json: serde_json::Value) -> Cleaner {
+        let configs: Vec<RuleConfig> = serde_json::from_value(json).unwrap();
+        Cleaner {
+            rules: configs
+                .into_iter()
+                .map(|c| Rule::from_config(c).unwrap())
+                .collect(),
+            provider: None,
+        }
+    }
+
+    fn meta(album: &str, tracks: &[(&str, &str)]) -> Metadata {
+        Metadata::new(
+            None,
+            album,
+            0,
+            "",
+            tracks.iter().map(|(t, a)| Track::new(*t, *a)).collect(),
+        )
+    }
+
+    #[test]
+    fn title_case_keeps_small_words_and_capitals() {
+        assert_eq!(
+            title_case("the return of the king"),
+            "The Return of the King"
+        );
+        assert_eq!(
+            title_case("back in black by AC/DC"),
+            "Back in Black by AC/DC"
+        );
+        assert_eq!(title_case("my iPhone song"), "My iPhone Song");
+        assert_eq!(title_case("part one: of mice"), "Part One: Of Mice");
+        assert_eq!(title_case("日本語の曲"), "日本語の曲");
+    }
+
+    #[test]
+    fn moves_featured_artist() {
+        let re = compile(FEATURED_PATTERN).unwrap();
+        let mut track = Track::new("Song (feat. X)", "A");
+        move_featured(&re, &mut track);
+        assert_eq!(track.title, "Song");
+        assert_eq!(track.artist, "A feat. X");
+
+        // already credited
+        let mut track = Track::new("Song [ft. X]", "A & X");
+        move_featured(&re, &mut track);
+        assert_eq!(track.title, "Song");
+        assert_eq!(track.artist, "A & X");
+    }
+
+    #[test]
+    fn normalizes_featuring_marks() {
+        let cleaner = rules(serde_json::json!([{"action": "normalize_feat"}]));
+        let mut meta = meta(
+            "",
+            &[("Song (Feat X)", "A featuring B"), ("Feather", "C ft. D")],
+        );
+        cleaner.clean(&mut meta);
+        assert_eq!(meta.tracks[0].title, "Song (feat. X)");
+        assert_eq!(meta.tracks[0].artist, "A feat. B");
+        assert_eq!(meta.tracks[1].title, "Feather");
+        assert_eq!(meta.tracks[1].artist, "C feat. D");
+    }
+
+    #[test]
+    fn conditions_limit_rules() {
+        let cleaner = rules(serde_json::json!([
+            {
+                "action": "replace",
+                "fields": ["title"],
+                "pattern": "\\s*\\(Live\\)$",
+                "when": {"field": "album", "matches": "Live"},
+            },
+            {
+                "action": "title_case",
+                "when": {"field": "artist", "matches": "^A$"},
+            },
+        ]));
+
+        let mut live = meta("Live at home", &[("one (Live)", "A"), ("two (Live)", "B")]);
+        cleaner.clean(&mut live);
+        assert_eq!(live.tracks[0].title, "One");
+        assert_eq!(live.tracks[1].title, "two");
+        // album condition on a track field holds only if it holds for every track
+        assert_eq!(live.album, "Live at home");
+
+        let mut studio = meta("Studio", &[("one (Live)", "B")]);
+        cleaner.clean(&mut studio);
+        assert_eq!(studio.tracks[0].title, "one (Live)");
+    }
+
+    #[test]
+    fn replace_keeps_unmatched_values() {
+        let cleaner = rules(serde_json::json!([
+            {"action": "replace", "pattern": "x", "replace": "y"},
+        ]));
+        let mut meta = meta(" Album ", &[("x ", "A")]);
+        cleaner.clean(&mut meta);
+        assert_eq!(meta.album, " Album ");
+        assert_eq!(meta.tracks[0].title, "y");
+    }
+
+    #[test]
+    fn rules_apply_only_to_their_providers() {
+        let cleaner = rules(serde_json::json!([
+            {"action": "replace", "fields": ["album"], "pattern": "A", "replace": "S", "providers": ["spotify"]},
+            {"action": "replace", "fields": ["album"], "pattern": "B", "replace": "L", "providers": ["local"]},
+        ]));
+
+        let mut fetched = meta("AB", &[]);
+        fetched.id = Some(SPOTIFY_ID.into());
+        cleaner.clean(&mut fetched);
+        assert_eq!(fetched.album, "SB");
+
+        let mut local = meta("AB", &[]);
+        cleaner.clean(&mut local);
+        assert_eq!(local.album, "AL");
+
+        let mut cleaner = cleaner;
+        cleaner.assume_provider(Provider::Spotify);
+        let mut tags = meta("AB", &[]);
+        cleaner.clean(&mut tags);
+        assert_eq!(tags.album, "SB");
+    }
+
+    #[test]
+    fn removes_spotify_remaster_suffix() {
+        let cleaner = Cleaner::default();
+        let titles = [
+            ("Song - Remastered 2011", "Song"),
+            ("Song - 2011 Remaster", "Song"),
+            ("Song (2009 Digital Remaster)", "Song"),
+            ("Song - Remastered Version", "Song"),
+            ("Remaster Blaster", "Remaster Blaster"),
+        ];
+        let mut fetched = meta("", &titles.map(|(t, _)| (t, "A")));
+        fetched.id = Some(SPOTIFY_ID.into());
+        cleaner.clean(&mut fetched);
+        for (track, (_, expected)) in fetched.tracks.iter().zip(titles) {
+            assert_eq!(track.title, expected);
+        }
+
+        // not from Spotify
+        let mut local = meta("", &[("Song - Remastered 2011", "A")]);
+        cleaner.clean(&mut local);
+        assert_eq!(local.tracks[0].title, "Song - Remastered 2011");
+    }
+}
@@ -0,0 +1,199 @@
+use std::fmt::{self, Write};
+
+use crate::{
+    fileio::Picture,
+    info_struct::{Metadata, Track},
+};
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDiff {
+    pub field: &'static str,
+    pub old: String,
+    pub new: String,
+}
+
+impl FieldDiff {
+    fn new<S: ToString>(field: &'static str, old: S, new: S) -> FieldDiff {
+        FieldDiff {
+            field,
+            old: old.to_string(),
+            new: new.to_string(),
+        }
+    }
+
+    pub fn changed(&self) -> bool {
+        self.old != self.new
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoverDiff {
+    Unchanged,
+    Changed,
+    Added,
+    Removed,
+}
+
+impl CoverDiff {
+    pub fn new(old: Option<&Picture>, new: Option<&Picture>) -> CoverDiff {
+        match (old, new) {
+            (None, None) => CoverDiff::Unchanged,
+            (None, Some(_)) => CoverDiff::Added,
+            (Some(_), None) => CoverDiff::Removed,
+            (Some(o), Some(n)) if o.raw == n.raw => CoverDiff::Unchanged,
+            (Some(_), Some(_)) => CoverDiff::Changed,
+        }
+    }
+
+    pub fn changed(&self) -> bool {
+        *self != CoverDiff::Unchanged
+    }
+}
+
+impl fmt::Display for CoverDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CoverDiff::Unchanged => write!(f, "unchanged"),
+            CoverDiff::Changed => write!(f, "changed"),
+            CoverDiff::Added => write!(f, "added"),
+            CoverDiff::Removed => write!(f, "removed"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackDiff {
+    /// track number, 1-origin
+    pub number: usize,
+    pub fields: Vec<FieldDiff>,
+    /// None if covers are not compared
+    pub cover: Option<CoverDiff>,
+}
+
+impl TrackDiff {
+    pub fn changed(&self) -> bool {
+        self.fields.iter().any(FieldDiff::changed) || matches!(self.cover, Some(c) if c.changed())
+    }
+}
+
+/// field-by-field difference between two metadata.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetaDiff {
+    pub album: Vec<FieldDiff>,
+    pub tracks: Vec<TrackDiff>,
+}
+
+impl MetaDiff {
+    pub fn new(old: &Metadata, new: &Metadata) -> MetaDiff {
+        let sort = |s: &Option<String>| s.clone().unwrap_or_default();
+        let album = vec![
+            FieldDiff::new("album", &old.album, &new.album),
+            FieldDiff::new("album sort", sort(&old.album_sort), sort(&new.album_sort)),
+            FieldDiff::new("date", old.date, new.date),
+            FieldDiff::new("genre", &old.genre, &new.genre),
+        ];
+
+        let empty = Track::default();
+        let tracks = (0..old.tracks.len().max(new.tracks.len()))
+            .map(|i| {
+                let o = old.tracks.get(i).unwrap_or(&empty);
+                let n = new.tracks.get(i).unwrap_or(&empty);
+
+                TrackDiff {
+                    number: i + 1,
+                    fields: vec![
+                        FieldDiff::new("title", &o.title, &n.title),
+                        FieldDiff::new("artist", &o.artist, &n.artist),
+                        FieldDiff::new("title sort", sort(&o.title_sort), sort(&n.title_sort)),
+                        FieldDiff::new("artist sort", sort(&o.artist_sort), sort(&n.artist_sort)),
+                    ],
+                    cover: None,
+                }
+            })
+            .collect();
+
+        MetaDiff { album, tracks }
+    }
+
+    /// compare current track numbers, indexed by track, with the numbers set on write.
+    pub fn with_track_numbers(mut self, old: &[u32]) -> MetaDiff {
+        for (track, old) in self.tracks.iter_mut().zip(old) {
+            track
+                .fields
+                .push(FieldDiff::new("track number", *old, track.number as u32));
+        }
+        self
+    }
+
+    /// compare covers of each track, `old` is indexed by track.
+    pub fn with_covers(mut self, old: &[Option<Picture>], new: Option<&Picture>) -> MetaDiff {
+        for (track, old) in self.tracks.iter_mut().zip(old) {
+            track.cover = Some(CoverDiff::new(old.as_ref(), new));
+        }
+        self
+    }
+
+    pub fn changed(&self) -> bool {
+        self.album.iter().any(FieldDiff::changed) || self.tracks.iter().any(TrackDiff::changed)
+    }
+
+    /// render diff, with ANSI colors if `color` is true.
+    /// unchanged tracks are omitted unless `verbose` is true.
+    pub fn render(&self, color: bool, verbose: bool) -> String {
+        let paint = |c: &str, s: String| {
+            if color {
+                format!("{}{}{}", c, s, RESET)
+            } else {
+                s
+            }
+        };
+
+        let mut result = String::new();
+        let field = |out: &mut String, indent: &str, field: &FieldDiff| {
+            if field.changed() {
+                let old = format!("{}- {}: {}", indent, field.field, field.old);
+                let new = format!("{}+ {}: {}", indent, field.field, field.new);
+                let _ = writeln!(out, "{}", paint(RED, old));
+                let _ = writeln!(out, "{}", paint(GREEN, new));
+            } else if verbose {
+                let _ = writeln!(out, "{}  {}: {}", indent, field.field, field.old);
+            }
+        };
+
+        for f in &self.album {
+            field(&mut result, "", f);
+        }
+        for track in self.tracks.iter().filter(|t| verbose || t.changed()) {
+            let _ = writeln!(result, "track {}:", track.number);
+            for f in &track.fields {
+                field(&mut result, "  ", f);
+            }
+            match track.cover {
+                Some(c) if c.changed() => {
+                    let line = format!("  ~ cover: {}", c);
+                    let _ = writeln!(result, "{}", paint(YELLOW, line));
+                }
+                Some(c) if verbose => {
+                    let _ = writeln!(result, "    cover: {}", c);
+                }
+                _ => (),
+            }
+        }
+
+        if !self.changed() {
+            result += "no changes\n";
+        }
+        result
+    }
+}
+
+impl fmt::Display for MetaDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render(false, true))
+    }
+}
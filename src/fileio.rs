@@ -1,11 +1,95 @@
+use std::path::Path;
+
+use crate::{patch::MetaPatch, traits::MetaFileIO};
+
+pub mod csv;
+pub use self::csv::{ColumnMap, Csv};
+
+pub mod cue;
+pub use cue::Cue;
+
+pub mod cue_image;
+pub use cue_image::CueImage;
+
+pub mod flac;
+pub use flac::{FlacFile, VorbisComment};
+
+pub mod id3;
+
 pub mod json;
 pub use json::Json;
 
+pub mod ogg;
+
 pub mod picture;
 pub use picture::Picture;
 
 pub mod taglib;
-pub use self::taglib::TagLib;
+pub use self::taglib::{read_disc, RawTags, TagLib};
 
 pub mod taglib_pic;
 pub use self::taglib_pic::TagLibPicture;
+
+pub mod toml;
+pub use self::toml::Toml;
+
+pub mod yaml;
+pub use yaml::Yaml;
+
+fn extension_of(path: &Path) -> String {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/// true if `path` has an extension of a metadata document format.
+pub fn is_meta_document<P: AsRef<Path>>(path: P) -> bool {
+    matches!(
+        extension_of(path.as_ref()).as_str(),
+        "json" | "cue" | "yaml" | "yml" | "toml" | "csv" | "tsv"
+    )
+}
+
+/// options for opening metadata documents.
+#[derive(Debug, Clone, Default)]
+pub struct DocumentOptions {
+    /// column mapping of CSV and TSV tracklists
+    pub columns: ColumnMap,
+}
+
+impl DocumentOptions {
+    /// metadata document at `path`, format chosen by extension:
+    /// CUE sheet for `.cue`, YAML for `.yaml` and `.yml`, TOML for `.toml`,
+    /// tracklist for `.csv` and `.tsv`, JSON otherwise.
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> Box<dyn MetaFileIO> {
+        match extension_of(path.as_ref()).as_str() {
+            "cue" => Box::new(Cue::new(path)),
+            "yaml" | "yml" => Box::new(Yaml::new(path)),
+            "toml" => Box::new(Toml::new(path)),
+            "csv" | "tsv" => Box::new(Csv::with_columns(path, self.columns.clone())),
+            _ => Box::new(Json::new(path)),
+        }
+    }
+
+    /// read metadata document at `path` as a patch, format chosen as in `open`.
+    /// only JSON and YAML can clear fields, other formats set or keep fields.
+    pub fn read_patch<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<MetaPatch> {
+        match extension_of(path.as_ref()).as_str() {
+            "yaml" | "yml" => Yaml::new(path).read_patch(),
+            "toml" => Toml::new(path).read_patch(),
+            "cue" | "csv" | "tsv" => Ok(MetaPatch::from(&self.open(path).read()?)),
+            _ => Json::new(path).read_patch(),
+        }
+    }
+}
+
+/// metadata document at `path` with default options, see [`DocumentOptions::open`].
+pub fn meta_document<P: AsRef<Path>>(path: P) -> Box<dyn MetaFileIO> {
+    DocumentOptions::default().open(path)
+}
+
+/// read metadata document at `path` as a patch with default options.
+pub fn read_patch<P: AsRef<Path>>(path: P) -> anyhow::Result<MetaPatch> {
+    DocumentOptions::default().read_patch(path)
+}
@@ -0,0 +1,504 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{
+    info_struct::{format_length, Metadata, Track},
+    traits::MetaFileIO,
+};
+
+/// field a column of a tracklist maps to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Album,
+    /// read as artist of tracks without one
+    AlbumArtist,
+    Date,
+    Genre,
+    Disc,
+    Track,
+    Title,
+    Artist,
+    Isrc,
+    /// `m:ss` or seconds
+    Length,
+}
+
+impl Column {
+    fn is_album_level(&self) -> bool {
+        matches!(
+            self,
+            Column::Album | Column::AlbumArtist | Column::Date | Column::Genre
+        )
+    }
+}
+
+impl FromStr for Column {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Column> {
+        match s.trim().to_lowercase().replace([' ', '_'], "").as_str() {
+            "album" => Ok(Column::Album),
+            "albumartist" => Ok(Column::AlbumArtist),
+            "date" | "year" => Ok(Column::Date),
+            "genre" => Ok(Column::Genre),
+            "disc" | "discnumber" => Ok(Column::Disc),
+            "track" | "tracknumber" | "no" | "#" => Ok(Column::Track),
+            "title" | "name" => Ok(Column::Title),
+            "artist" | "performer" => Ok(Column::Artist),
+            "isrc" => Ok(Column::Isrc),
+            "length" | "time" | "duration" => Ok(Column::Length),
+            _ => anyhow::bail!("Error: unknown column: {}", s),
+        }
+    }
+}
+
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Column::Album => "album",
+            Column::AlbumArtist => "albumartist",
+            Column::Date => "date",
+            Column::Genre => "genre",
+            Column::Disc => "disc",
+            Column::Track => "track",
+            Column::Title => "title",
+            Column::Artist => "artist",
+            Column::Isrc => "isrc",
+            Column::Length => "length",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// column names of a tracklist mapped to fields, like `曲名=title,歌手=artist`.
+/// names not in the map are matched against field names, and other columns are kept as is.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ColumnMap {
+    names: Vec<(String, Column)>,
+}
+
+impl FromStr for ColumnMap {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<ColumnMap> {
+        let mut names = Vec::new();
+        for pair in s.split(',').filter(|p| !p.trim().is_empty()) {
+            let (name, field) = pair.split_once('=').ok_or(anyhow::anyhow!(
+                "Error: column mapping must be NAME=FIELD: {}",
+                pair
+            ))?;
+            names.push((name.trim().to_lowercase(), Column::from_str(field)?));
+        }
+        Ok(ColumnMap { names })
+    }
+}
+
+impl ColumnMap {
+    pub fn column(&self, name: &str) -> Option<Column> {
+        let name = name.trim().to_lowercase();
+        self.names
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, c)| *c)
+            .or_else(|| Column::from_str(&name).ok())
+    }
+}
+
+/// spreadsheet tracklist in CSV, or TSV for `.tsv` files.
+///
+/// each row after the column names is a track. album-level fields are either repeated
+/// in columns, or placed in a header block of `name,value` rows before the column names.
+/// a header block is only recognized before at least 3 column names, so that a tracklist
+/// like `album,comment` is not mistaken for one.
+/// writing to an existing file keeps its layout and the values of unmapped columns.
+pub struct Csv {
+    path: PathBuf,
+    columns: ColumnMap,
+}
+
+/// parsed tracklist: header block, column names and rows.
+struct Sheet {
+    header: Vec<Vec<String>>,
+    names: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+fn parse_length(value: &str) -> Option<u32> {
+    value.split(':').try_fold(0, |acc, part| {
+        u32::from_str(part.trim()).ok().map(|n| acc * 60 + n)
+    })
+}
+
+impl Csv {
+    pub fn new<P: AsRef<Path>>(path: P) -> Csv {
+        Csv::with_columns(path, ColumnMap::default())
+    }
+
+    pub fn with_columns<P: AsRef<Path>>(path: P, columns: ColumnMap) -> Csv {
+        Csv {
+            path: path.as_ref().into(),
+            columns,
+        }
+    }
+
+    pub fn to_string(meta: &Metadata) -> anyhow::Result<String> {
+        let sheet = fill_sheet(Csv::new_sheet(meta), meta, &ColumnMap::default());
+        sheet_to_string(&sheet, b',')
+    }
+
+    fn delimiter(&self) -> u8 {
+        match self.path.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("tsv") => b'\t',
+            _ => b',',
+        }
+    }
+
+    /// true if `row` is `name,value` of an album-level field, padded with empty cells.
+    fn is_header_row(&self, row: &[String]) -> bool {
+        match row {
+            [name, value, rest @ ..] => {
+                matches!(self.columns.column(name), Some(c) if c.is_album_level())
+                    && self.columns.column(value).is_none()
+                    && rest.iter().all(|c| c.trim().is_empty())
+            }
+            _ => false,
+        }
+    }
+
+    fn read_sheet(&self) -> anyhow::Result<Sheet> {
+        let mut reader = ::csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .delimiter(self.delimiter())
+            .from_path(&self.path)?;
+
+        let mut header = Vec::new();
+        // `name,value` rows, a header block only if at least 3 column names follow them
+        let mut pending = Vec::new();
+        let mut names = None;
+        let mut rows = Vec::new();
+
+        for record in reader.records() {
+            let row: Vec<String> = record?.iter().map(str::to_string).collect();
+            let blank = row.iter().all(|c| c.trim().is_empty());
+
+            match names {
+                None if blank => (),
+                None if self.is_header_row(&row) => pending.push(row),
+                None if pending.is_empty()
+                    || row.iter().filter(|c| !c.trim().is_empty()).count() >= 3 =>
+                {
+                    header.append(&mut pending);
+                    names = Some(row);
+                }
+                None => {
+                    pending.push(row);
+                    names = Some(pending.remove(0));
+                    rows.append(&mut pending);
+                }
+                Some(_) if blank => (),
+                Some(_) => rows.push(row),
+            }
+        }
+        if names.is_none() && !pending.is_empty() {
+            // like `album,comment`, column names without tracks
+            names = Some(pending.remove(0));
+            rows.append(&mut pending);
+        }
+
+        let names = names.ok_or(anyhow::anyhow!(
+            "Error: no column names found in {}",
+            self.path.display()
+        ))?;
+
+        // rows may be in any order, sort by disc and track number
+        let position = |column: Column| {
+            names
+                .iter()
+                .position(|n| self.columns.column(n) == Some(column))
+        };
+        let (disc, track) = (position(Column::Disc), position(Column::Track));
+        let number = |row: &Vec<String>, idx: Option<usize>| {
+            idx.and_then(|i| row.get(i))
+                .and_then(|v| v.trim().parse::<u32>().ok())
+        };
+        rows.sort_by_key(|row| (number(row, disc).unwrap_or(1), number(row, track)));
+
+        Ok(Sheet {
+            header,
+            names,
+            rows,
+        })
+    }
+
+    /// layout of a new tracklist for `meta`, with album-level fields repeated in columns.
+    fn new_sheet(meta: &Metadata) -> Sheet {
+        let mut columns = Vec::new();
+        if meta.tracks.iter().any(|t| t.disc.is_some()) {
+            columns.push(Column::Disc);
+        }
+        columns.extend([
+            Column::Track,
+            Column::Title,
+            Column::Artist,
+            Column::Album,
+            Column::Date,
+            Column::Genre,
+        ]);
+        if meta.tracks.iter().any(|t| t.isrc.is_some()) {
+            columns.push(Column::Isrc);
+        }
+        if meta.tracks.iter().any(|t| t.length.is_some()) {
+            columns.push(Column::Length);
+        }
+
+        Sheet {
+            header: Vec::new(),
+            names: columns.iter().map(Column::to_string).collect(),
+            rows: Vec::new(),
+        }
+    }
+}
+
+/// replace values of mapped cells in `sheet` with `meta`, adding or removing rows to fit tracks.
+fn fill_sheet(mut sheet: Sheet, meta: &Metadata, columns: &ColumnMap) -> Sheet {
+    let mapped: Vec<_> = sheet.names.iter().map(|n| columns.column(n)).collect();
+
+    // album artist is kept, as metadata has no album artist
+    let fill = |cell: &mut String, column: Column, index: usize| {
+        if column != Column::AlbumArtist || cell.trim().is_empty() {
+            *cell = value_of(meta, index, column);
+        }
+    };
+
+    if !meta.tracks.is_empty() {
+        for row in &mut sheet.header {
+            if let Some(column) = columns.column(&row[0]) {
+                fill(&mut row[1], column, 0);
+            }
+        }
+    }
+
+    sheet.rows.resize(meta.tracks.len(), Vec::new());
+    for (i, row) in sheet.rows.iter_mut().enumerate() {
+        row.resize(mapped.len().max(row.len()), String::new());
+        for (cell, column) in row.iter_mut().zip(&mapped) {
+            if let Some(column) = column {
+                fill(cell, *column, i);
+            }
+        }
+    }
+    sheet
+}
+
+fn sheet_to_string(sheet: &Sheet, delimiter: u8) -> anyhow::Result<String> {
+    let records = |rows: &[Vec<String>]| -> anyhow::Result<String> {
+        let mut writer = ::csv::WriterBuilder::new()
+            .flexible(true)
+            .delimiter(delimiter)
+            .from_writer(Vec::new());
+        for row in rows {
+            writer.write_record(row)?;
+        }
+        Ok(String::from_utf8(writer.into_inner()?)?)
+    };
+
+    let mut result = records(&sheet.header)?;
+    if !sheet.header.is_empty() {
+        // blank line between header block and column names
+        result.push('\n');
+    }
+    result += &records(std::slice::from_ref(&sheet.names))?;
+    result += &records(&sheet.rows)?;
+    Ok(result)
+}
+
+/// value of `column` for track `index` of `meta`.
+fn value_of(meta: &Metadata, index: usize, column: Column) -> String {
+    let track = &meta.tracks[index];
+    match column {
+        Column::Album => meta.album.clone(),
+        Column::AlbumArtist => meta.album_artist(),
+        Column::Date if meta.date == 0 => String::new(),
+        Column::Date => meta.date.to_string(),
+        Column::Genre => meta.genre.clone(),
+        Column::Disc => track.disc.map(|d| d.to_string()).unwrap_or_default(),
+        // numbered within each disc
+        Column::Track => {
+            let count = meta.tracks[..index]
+                .iter()
+                .filter(|t| t.disc == track.disc)
+                .count();
+            (count + 1).to_string()
+        }
+        Column::Title => track.title.clone(),
+        Column::Artist => track.artist.clone(),
+        Column::Isrc => track.isrc.clone().unwrap_or_default(),
+        Column::Length => track.length.map(format_length).unwrap_or_default(),
+    }
+}
+
+impl MetaFileIO for Csv {
+    fn read(&self) -> anyhow::Result<Metadata> {
+        let sheet = self.read_sheet()?;
+        let columns: Vec<_> = sheet.names.iter().map(|n| self.columns.column(n)).collect();
+
+        let mut meta = Metadata::default();
+        let mut album_artist = String::new();
+        let mut set_album_field = |column: Column, value: &str| {
+            let value = value.trim();
+            match column {
+                Column::Album if meta.album.is_empty() => meta.album = value.into(),
+                Column::Genre if meta.genre.is_empty() => meta.genre = value.into(),
+                Column::Date if meta.date == 0 => {
+                    let year: String = value.chars().take(4).collect();
+                    meta.date = year.parse().unwrap_or(0);
+                }
+                Column::AlbumArtist if album_artist.is_empty() => album_artist = value.into(),
+                _ => (),
+            }
+        };
+
+        for row in &sheet.header {
+            if let Some(column) = self.columns.column(&row[0]) {
+                set_album_field(column, &row[1]);
+            }
+        }
+
+        for row in &sheet.rows {
+            let mut track = Track::default();
+            for (column, value) in columns.iter().zip(row) {
+                let value = value.trim();
+                match column {
+                    Some(c) if c.is_album_level() => set_album_field(*c, value),
+                    Some(Column::Disc) => track.disc = value.parse().ok(),
+                    Some(Column::Title) => track.title = value.into(),
+                    Some(Column::Artist) => track.artist = value.into(),
+                    Some(Column::Isrc) if !value.is_empty() => track.isrc = Some(value.into()),
+                    Some(Column::Length) => track.length = parse_length(value),
+                    _ => (),
+                }
+            }
+            meta.tracks.push(track);
+        }
+
+        for track in meta.tracks.iter_mut().filter(|t| t.artist.is_empty()) {
+            track.artist = album_artist.clone();
+        }
+
+        Ok(meta)
+    }
+
+    fn write(&self, meta: &Metadata) -> anyhow::Result<()> {
+        let sheet = if self.path.exists() {
+            self.read_sheet()?
+        } else {
+            Csv::new_sheet(meta)
+        };
+        let sheet = fill_sheet(sheet, meta, &self.columns);
+
+        std::fs::write(&self.path, sheet_to_string(&sheet, self.delimiter())?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str, data: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("music_info_{}_{}", std::process::id(), name));
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    fn read(name: &str, data: &str, columns: &str) -> Metadata {
+        let path = temp_file(name, data);
+        let result = Csv::with_columns(&path, columns.parse().unwrap()).read();
+        std::fs::remove_file(&path).unwrap();
+        result.unwrap()
+    }
+
+    #[test]
+    fn reads_header_block() {
+        let meta = read(
+            "csv_header.csv",
+            "album,My Album\nalbum artist,Band\ndate,2001-05-01\n\ntrack,title,artist\n2,Two,\n1,One,Guest\n",
+            "",
+        );
+        assert_eq!(meta.album, "My Album");
+        assert_eq!(meta.date, 2001);
+        // rows are sorted by track number
+        assert_eq!(meta.tracks[0].title, "One");
+        assert_eq!(meta.tracks[0].artist, "Guest");
+        assert_eq!(meta.tracks[1].artist, "Band");
+    }
+
+    #[test]
+    fn reads_repeated_album_columns() {
+        let meta = read(
+            "csv_columns.csv",
+            "disc,no,name,album,genre,time\n2,1,Three,Album,Rock,1:02\n1,1,One,Album,Rock,61\n",
+            "",
+        );
+        assert_eq!(meta.album, "Album");
+        assert_eq!(meta.genre, "Rock");
+        assert_eq!(meta.tracks[0].disc, Some(1));
+        assert_eq!(meta.tracks[0].length, Some(61));
+        assert_eq!(meta.tracks[1].title, "Three");
+        assert_eq!(meta.tracks[1].length, Some(62));
+    }
+
+    #[test]
+    fn reads_tsv() {
+        let meta = read("csv_tabs.tsv", "title\tartist\nOne, Two\tA\n", "");
+        assert_eq!(meta.tracks[0].title, "One, Two");
+        assert_eq!(meta.tracks[0].artist, "A");
+    }
+
+    #[test]
+    fn reads_mapped_names() {
+        let meta = read(
+            "csv_mapped.csv",
+            "曲名,歌手,備考\n一,A,x\n",
+            "曲名=title, 歌手=artist",
+        );
+        assert_eq!(meta.tracks[0].title, "一");
+        assert_eq!(meta.tracks[0].artist, "A");
+    }
+
+    #[test]
+    fn two_column_tracklist_is_not_a_header_block() {
+        let meta = read("csv_two.csv", "album,comment\nAlbum,nice\n", "");
+        assert_eq!(meta.album, "Album");
+        assert_eq!(meta.tracks.len(), 1);
+    }
+
+    #[test]
+    fn write_keeps_layout_and_extra_columns() {
+        let path = temp_file(
+            "csv_round_trip.csv",
+            "album,Old\n\ntrack,title,note\n1,Old One,first\n2,Old Two,second\n",
+        );
+        let csv = Csv::new(&path);
+
+        let mut meta = csv.read().unwrap();
+        meta.album = "New".into();
+        meta.tracks[1].title = "New Two".into();
+        csv.write(&meta).unwrap();
+
+        let result = csv.read().unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(result.album, "New");
+        assert_eq!(result.tracks[1].title, "New Two");
+        assert_eq!(
+            text,
+            "album,New\n\ntrack,title,note\n1,Old One,first\n2,New Two,second\n"
+        );
+    }
+}
@@ -0,0 +1,380 @@
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    info_struct::{Metadata, Track},
+    toc::{decode_log, msf_to_frames},
+    traits::MetaFileIO,
+};
+
+/// CUE sheet as a metadata document.
+///
+/// maps `TITLE`, `PERFORMER`, `REM DATE`, `REM GENRE`, `REM DISCID` of the sheet to album fields,
+/// and `TITLE`, `PERFORMER`, `ISRC` of each `TRACK` to tracks.
+/// writing to an existing sheet only replaces these commands, keeping files and indexes.
+pub struct Cue {
+    path: PathBuf,
+}
+
+/// quote a value, CUE sheets have no escape for `"`.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "'"))
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    match value.strip_prefix('"') {
+        Some(v) => v.strip_suffix('"').unwrap_or(v).to_string(),
+        None => value.to_string(),
+    }
+}
+
+/// split a line to upper-cased command and the rest.
+/// for `REM`, the command is `REM <NAME>`.
+fn command(line: &str) -> (String, &str) {
+    let line = line.trim();
+    let (cmd, arg) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let cmd = cmd.to_uppercase();
+
+    if cmd == "REM" {
+        let arg = arg.trim_start();
+        let (name, rest) = arg.split_once(char::is_whitespace).unwrap_or((arg, ""));
+        (format!("REM {}", name.to_uppercase()), rest.trim())
+    } else {
+        (cmd, arg.trim())
+    }
+}
+
+/// commands written from metadata, which are replaced on write.
+fn is_meta_command(cmd: &str) -> bool {
+    matches!(
+        cmd,
+        "TITLE" | "PERFORMER" | "ISRC" | "REM DATE" | "REM GENRE" | "REM DISCID"
+    )
+}
+
+/// `mm:ss:ff` of `frames`
+fn frames_to_msf(frames: u32) -> String {
+    format!(
+        "{:02}:{:02}:{:02}",
+        frames / 75 / 60,
+        frames / 75 % 60,
+        frames % 75
+    )
+}
+
+/// `performer` is the album performer to keep, album artist of `meta` is used if None.
+fn header_lines(meta: &Metadata, performer: Option<&str>) -> Vec<String> {
+    let mut result = Vec::new();
+    if !meta.genre.is_empty() {
+        result.push(format!("REM GENRE {}", quote(&meta.genre)));
+    }
+    if meta.date != 0 {
+        result.push(format!("REM DATE {}", meta.date));
+    }
+    if let Some(id) = &meta.disc_id {
+        result.push(format!("REM DISCID {}", id));
+    }
+    let performer = performer.map_or_else(|| meta.album_artist(), str::to_string);
+    result.push(format!("PERFORMER {}", quote(&performer)));
+    result.push(format!("TITLE {}", quote(&meta.album)));
+    result
+}
+
+fn track_lines(track: &Track) -> Vec<String> {
+    let mut result = vec![
+        format!("    TITLE {}", quote(&track.title)),
+        format!("    PERFORMER {}", quote(&track.artist)),
+    ];
+    if let Some(isrc) = &track.isrc {
+        result.push(format!("    ISRC {}", isrc));
+    }
+    result
+}
+
+impl Cue {
+    pub fn new<P: AsRef<Path>>(path: P) -> Cue {
+        Cue {
+            path: path.as_ref().into(),
+        }
+    }
+
+    pub fn to_string(meta: &Metadata) -> anyhow::Result<String> {
+        Cue::generate(meta, "CDImage.wav")
+    }
+
+    /// new sheet of a single file named `file`, with indexes computed from track lengths.
+    pub(crate) fn generate(meta: &Metadata, file: &str) -> anyhow::Result<String> {
+        let mut result = header_lines(meta, None).join("\n");
+        let _ = write!(result, "\nFILE {} WAVE\n", quote(file));
+
+        let mut frames = 0;
+        for (i, track) in meta.tracks.iter().enumerate() {
+            let _ = writeln!(result, "  TRACK {:02} AUDIO", i + 1);
+            for line in track_lines(track) {
+                let _ = writeln!(result, "{}", line);
+            }
+            let _ = writeln!(result, "    INDEX 01 {}", frames_to_msf(frames));
+
+            match track.length {
+                Some(length) => frames += length * 75,
+                None if i + 1 < meta.tracks.len() => anyhow::bail!(
+                    "Error: length of track {} is needed to generate CUE sheet",
+                    i + 1
+                ),
+                None => (),
+            }
+        }
+        Ok(result)
+    }
+
+    /// replace metadata commands of `sheet` with `meta`.
+    /// album performer of the sheet is kept, as metadata has no album artist.
+    pub(crate) fn merge(sheet: &str, meta: &Metadata) -> String {
+        let performer = sheet
+            .lines()
+            .map(command)
+            .take_while(|(cmd, _)| cmd != "TRACK")
+            .find(|(cmd, _)| cmd == "PERFORMER")
+            .map(|(_, arg)| unquote(arg));
+
+        let mut result = header_lines(meta, performer.as_deref());
+        let mut track_idx = None;
+
+        for line in sheet.lines() {
+            let (cmd, _) = command(line);
+            if is_meta_command(&cmd) {
+                continue;
+            }
+            result.push(line.trim_end().to_string());
+
+            if cmd == "TRACK" {
+                let idx = track_idx.map_or(0, |i| i + 1);
+                track_idx = Some(idx);
+                match meta.tracks.get(idx) {
+                    Some(track) => result.extend(track_lines(track)),
+                    None => println!("Warning: no metadata for track {} of CUE sheet", idx + 1),
+                }
+            }
+        }
+
+        let sheet_tracks = track_idx.map_or(0, |i| i + 1);
+        if meta.tracks.len() > sheet_tracks {
+            println!(
+                "Warning: CUE sheet has {} tracks, metadata of {} tracks is ignored",
+                sheet_tracks,
+                meta.tracks.len() - sheet_tracks
+            );
+        }
+
+        result.join("\n") + "\n"
+    }
+
+    /// number of each TRACK of `sheet`, its position if the number is broken.
+    pub(crate) fn track_numbers(sheet: &str) -> Vec<usize> {
+        sheet
+            .lines()
+            .map(command)
+            .filter(|(cmd, _)| cmd == "TRACK")
+            .enumerate()
+            .map(|(i, (_, arg))| {
+                arg.split_whitespace()
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or(i + 1)
+            })
+            .collect()
+    }
+
+    /// parse `sheet`, with INDEX 01 of each track in frames from the start of its file.
+    pub(crate) fn parse(sheet: &str) -> anyhow::Result<(Metadata, Vec<Option<u32>>)> {
+        let mut meta = Metadata::default();
+        let mut performer = String::new();
+        // (file number, INDEX 01 frames) of each track, to compute lengths
+        let mut starts: Vec<Option<(usize, u32)>> = Vec::new();
+        let mut file_no = 0;
+
+        for line in sheet.lines() {
+            let (cmd, arg) = command(line);
+            let track = meta.tracks.last_mut();
+
+            match (cmd.as_str(), track) {
+                ("FILE", _) => file_no += 1,
+                ("TRACK", _) => {
+                    meta.tracks.push(Track::default());
+                    starts.push(None);
+                }
+                ("TITLE", None) => meta.album = unquote(arg),
+                ("PERFORMER", None) => performer = unquote(arg),
+                ("REM DATE", None) => {
+                    let year: String = unquote(arg).chars().take(4).collect();
+                    meta.date = year.parse().unwrap_or(0);
+                }
+                ("REM GENRE", None) => meta.genre = unquote(arg),
+                ("REM DISCID", None) => meta.disc_id = Some(unquote(arg)),
+                ("TITLE", Some(track)) => track.title = unquote(arg),
+                ("PERFORMER", Some(track)) => track.artist = unquote(arg),
+                ("ISRC", Some(track)) => track.isrc = Some(unquote(arg)),
+                ("INDEX", Some(_)) => {
+                    let (num, time) = arg.split_once(char::is_whitespace).unwrap_or((arg, ""));
+                    if num == "01" {
+                        *starts.last_mut().unwrap() = Some((file_no, msf_to_frames(time.trim())?));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        if meta.tracks.is_empty() {
+            anyhow::bail!("Error: no TRACK found in CUE sheet")
+        }
+
+        for (i, track) in meta.tracks.iter_mut().enumerate() {
+            if track.artist.is_empty() {
+                track.artist = performer.clone();
+            }
+            // length is known if the next track starts in the same file
+            if let (Some(Some((f1, a))), Some(Some((f2, b)))) = (starts.get(i), starts.get(i + 1)) {
+                if f1 == f2 && b > a {
+                    track.length = Some((b - a + 37) / 75);
+                }
+            }
+        }
+
+        let starts = starts.into_iter().map(|s| s.map(|s| s.1)).collect();
+        Ok((meta, starts))
+    }
+}
+
+impl MetaFileIO for Cue {
+    fn read(&self) -> anyhow::Result<Metadata> {
+        let text = decode_log(&std::fs::read(&self.path)?);
+        Cue::parse(&text)
+            .map(|x| x.0)
+            .map_err(|e| anyhow::anyhow!("{} in {}", e, self.path.display()))
+    }
+
+    fn write(&self, meta: &Metadata) -> anyhow::Result<()> {
+        let sheet = if self.path.exists() {
+            let text = decode_log(&std::fs::read(&self.path)?);
+            Cue::merge(&text, meta)
+        } else {
+            let file = self
+                .path
+                .with_extension("wav")
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "CDImage.wav".into());
+            Cue::generate(meta, &file)?
+        };
+
+        std::fs::write(&self.path, sheet)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHEET: &str = r#"REM GENRE "Rock"
+rem date 2001-05-01
+REM DISCID 860B640B
+PERFORMER "Album Artist"
+TITLE "Say "Hello""
+FILE "CD1.wav" WAVE
+  TRACK 01 AUDIO
+    TITLE "One"
+    PERFORMER "Guest"
+    ISRC JPXX00100001
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE Two
+    INDEX 00 00:58:00
+    INDEX 01 01:00:00
+FILE "CD2.wav" WAVE
+  TRACK 03 AUDIO
+    TITLE "Three"
+    INDEX 01 00:00:00
+"#;
+
+    #[test]
+    fn parses_quoted_and_bare_values() {
+        let (meta, _) = Cue::parse(SHEET).unwrap();
+        assert_eq!(meta.album, r#"Say "Hello""#);
+        assert_eq!(meta.genre, "Rock");
+        assert_eq!(meta.date, 2001);
+        assert_eq!(meta.disc_id.as_deref(), Some("860B640B"));
+        assert_eq!(meta.tracks[0].isrc.as_deref(), Some("JPXX00100001"));
+        assert_eq!(meta.tracks[1].title, "Two");
+    }
+
+    #[test]
+    fn tracks_without_performer_use_album_performer() {
+        let (meta, _) = Cue::parse(SHEET).unwrap();
+        assert_eq!(meta.tracks[0].artist, "Guest");
+        assert_eq!(meta.tracks[1].artist, "Album Artist");
+    }
+
+    #[test]
+    fn starts_are_index_01_of_each_file() {
+        let (meta, starts) = Cue::parse(SHEET).unwrap();
+        assert_eq!(starts, vec![Some(0), Some(60 * 75), Some(0)]);
+        // INDEX 00 pregap belongs to the previous track
+        assert_eq!(meta.tracks[0].length, Some(60));
+        // the last track of a file has no known end
+        assert_eq!(meta.tracks[1].length, None);
+        assert_eq!(meta.tracks[2].length, None);
+    }
+
+    #[test]
+    fn track_numbers_are_read_from_sheet() {
+        let sheet = "FILE a.wav WAVE\n  TRACK 05 AUDIO\n  TRACK 6 AUDIO\n  TRACK ?? AUDIO\n";
+        assert_eq!(Cue::track_numbers(sheet), vec![5, 6, 3]);
+    }
+
+    #[test]
+    fn sheet_without_tracks_is_rejected() {
+        assert!(Cue::parse("TITLE \"Album\"\nFILE \"a.wav\" WAVE\n").is_err());
+    }
+
+    #[test]
+    fn generated_sheet_reads_back() {
+        let mut first = Track::new("One", "Artist");
+        first.length = Some(100);
+        let mut second = Track::new("Two \"2\"", "Artist");
+        second.length = Some(200);
+        let meta = Metadata::new(None, "Album", 2020, "Pop", vec![first, second]);
+
+        let (result, starts) = Cue::parse(&Cue::generate(&meta, "a.wav").unwrap()).unwrap();
+        assert_eq!(result.album, "Album");
+        assert_eq!(result.date, 2020);
+        assert_eq!(result.genre, "Pop");
+        assert_eq!(result.tracks[0].title, "One");
+        assert_eq!(result.tracks[0].length, Some(100));
+        // CUE sheets can not escape quotes
+        assert_eq!(result.tracks[1].title, "Two '2'");
+        assert_eq!(result.tracks[1].artist, "Artist");
+        assert_eq!(starts, vec![Some(0), Some(100 * 75)]);
+    }
+
+    #[test]
+    fn merge_keeps_files_and_indexes() {
+        let (mut meta, _) = Cue::parse(SHEET).unwrap();
+        meta.album = "New".into();
+        meta.tracks[1].title = "Zwei".into();
+
+        let merged = Cue::merge(SHEET, &meta);
+        assert!(merged.contains("TITLE \"New\""));
+        assert!(merged.contains("PERFORMER \"Album Artist\""));
+        assert!(merged.contains("FILE \"CD2.wav\" WAVE"));
+        assert!(merged.contains("    INDEX 00 00:58:00"));
+
+        let (result, starts) = Cue::parse(&merged).unwrap();
+        assert_eq!(result.tracks[1].title, "Zwei");
+        assert_eq!(result.tracks[0].artist, "Guest");
+        assert_eq!(starts, vec![Some(0), Some(60 * 75), Some(0)]);
+    }
+}
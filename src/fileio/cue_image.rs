@@ -0,0 +1,343 @@
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    fileio::{
+        cue::Cue,
+        flac::{CueSheetTrack, FlacFile},
+    },
+    info_struct::Metadata,
+    patch::MetaPatch,
+    toc::decode_log,
+    traits::MetaFileIO,
+};
+
+/// Vorbis comment holding a CUE sheet embedded in the image
+const CUESHEET_TAG: &str = "CUESHEET";
+
+/// single FLAC file holding a whole disc, with tracks defined by a CUE sheet.
+///
+/// track structure is taken from the external CUE sheet, the embedded `CUESHEET` comment
+/// or the CUESHEET block, in this order.
+/// album fields are stored as usual tags, and tracks as `TRACKNN_TITLE`, `TRACKNN_ARTIST`
+/// and `TRACKNN_ISRC`, where `NN` is the TRACK number of the sheet.
+pub struct CueImage {
+    image: PathBuf,
+    cue: Option<PathBuf>,
+}
+
+fn track_key(number: usize, field: &str) -> String {
+    format!("TRACK{:02}_{}", number, field)
+}
+
+/// true for keys like `TRACK01_TITLE`.
+fn is_track_key(key: &str) -> bool {
+    match key
+        .strip_prefix("TRACK")
+        .and_then(|rest| rest.split_once('_'))
+    {
+        Some((n, _)) => !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()),
+        None => false,
+    }
+}
+
+/// CUE sheet of a single `file` with INDEX 01 of CUESHEET block tracks.
+fn sheet_from_block(file: &str, tracks: &[CueSheetTrack], sample_rate: u32) -> String {
+    let mut result = format!("FILE \"{}\" WAVE\n", file);
+    for track in tracks {
+        let frames = track.start * 75 / sample_rate.max(1) as u64;
+        let _ = writeln!(result, "  TRACK {:02} AUDIO", track.number);
+        if let Some(isrc) = &track.isrc {
+            let _ = writeln!(result, "    ISRC {}", isrc);
+        }
+        let _ = writeln!(
+            result,
+            "    INDEX 01 {:02}:{:02}:{:02}",
+            frames / 75 / 60,
+            frames / 75 % 60,
+            frames % 75
+        );
+    }
+    result
+}
+
+impl CueImage {
+    /// image `image` split by CUE sheet `cue`, or by the embedded cuesheet if None.
+    pub fn new<P: AsRef<Path>>(image: P, cue: Option<P>) -> CueImage {
+        CueImage {
+            image: image.as_ref().into(),
+            cue: cue.map(|c| c.as_ref().into()),
+        }
+    }
+
+    /// image with the CUE sheet of the same name next to it, if it exists.
+    pub fn find<P: AsRef<Path>>(image: P) -> CueImage {
+        let image = image.as_ref();
+        let cue = image.with_extension("cue");
+        CueImage {
+            image: image.into(),
+            cue: cue.exists().then_some(cue),
+        }
+    }
+
+    pub fn image(&self) -> &Path {
+        &self.image
+    }
+
+    /// external CUE sheet, None if the embedded one is used.
+    pub fn cue(&self) -> Option<&Path> {
+        self.cue.as_deref()
+    }
+
+    fn file_name(&self) -> String {
+        self.image
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// CUE sheet text, from external file, embedded comment or CUESHEET block.
+    fn sheet(&self, flac: &FlacFile) -> anyhow::Result<String> {
+        if let Some(cue) = &self.cue {
+            return Ok(decode_log(&std::fs::read(cue)?));
+        }
+        if let Some(sheet) = flac.comments()?.get(CUESHEET_TAG) {
+            return Ok(sheet.to_string());
+        }
+        match flac.cuesheet()? {
+            Some(tracks) => Ok(sheet_from_block(
+                &self.file_name(),
+                &tracks,
+                flac.stream_info().0,
+            )),
+            None => anyhow::bail!("Error: no CUE sheet found for {}", self.image.display()),
+        }
+    }
+
+    /// write only the fields changed by `patch`.
+    pub fn apply(&self, patch: &MetaPatch) -> anyhow::Result<()> {
+        let current = self.read()?;
+        self.write(&patch.apply(&current))
+    }
+}
+
+impl MetaFileIO for CueImage {
+    fn read(&self) -> anyhow::Result<Metadata> {
+        let flac = FlacFile::open(&self.image)?;
+        let comments = flac.comments()?;
+        let sheet = self.sheet(&flac)?;
+        let (mut meta, starts) = Cue::parse(&sheet)?;
+        let numbers = Cue::track_numbers(&sheet);
+        let (sample_rate, total_samples) = flac.stream_info();
+
+        // lengths from index points, last track ends at the end of the image
+        let total_frames = (total_samples * 75 / sample_rate.max(1) as u64) as u32;
+        for (i, track) in meta.tracks.iter_mut().enumerate() {
+            let start = starts.get(i).copied().flatten();
+            let end = match starts.get(i + 1) {
+                Some(s) => *s,
+                None => Some(total_frames),
+            };
+            if let (Some(start), Some(end)) = (start, end) {
+                if end > start {
+                    track.length = Some((end - start + 37) / 75);
+                }
+            }
+        }
+
+        if let Some(album) = comments.get("ALBUM") {
+            meta.album = album.into();
+        }
+        if let Some(genre) = comments.get("GENRE") {
+            meta.genre = genre.into();
+        }
+        meta.album_sort = comments.get("ALBUMSORT").map(String::from);
+        if let Some(date) = comments.get("DATE") {
+            let year: String = date.chars().take(4).collect();
+            meta.date = year.parse().unwrap_or(meta.date);
+        }
+
+        let album_artist = comments
+            .get("ALBUMARTIST")
+            .or_else(|| comments.get("ARTIST"));
+        for (track, number) in meta.tracks.iter_mut().zip(numbers) {
+            if let Some(title) = comments.get(&track_key(number, "TITLE")) {
+                track.title = title.into();
+            }
+            match (comments.get(&track_key(number, "ARTIST")), album_artist) {
+                (Some(artist), _) => track.artist = artist.into(),
+                (None, Some(artist)) if track.artist.is_empty() => track.artist = artist.into(),
+                _ => (),
+            }
+            if let Some(isrc) = comments.get(&track_key(number, "ISRC")) {
+                track.isrc = Some(isrc.into());
+            }
+            track.title_sort = comments
+                .get(&track_key(number, "TITLESORT"))
+                .map(String::from);
+            track.artist_sort = comments
+                .get(&track_key(number, "ARTISTSORT"))
+                .map(String::from);
+        }
+
+        Ok(meta)
+    }
+
+    fn write(&self, meta: &Metadata) -> anyhow::Result<()> {
+        let mut flac = FlacFile::open(&self.image)?;
+        let mut comments = flac.comments()?;
+
+        let sheet = Cue::merge(&self.sheet(&flac)?, meta);
+        // keys are numbered by TRACK of the sheet, which may not start at 1
+        let numbers = Cue::track_numbers(&sheet);
+        if let Some(cue) = &self.cue {
+            std::fs::write(cue, &sheet)?;
+        }
+        comments.set(CUESHEET_TAG, &sheet);
+
+        comments.set("ALBUM", &meta.album);
+        comments.set("ALBUMSORT", meta.album_sort.as_deref().unwrap_or_default());
+        comments.set("ALBUMARTIST", &meta.album_artist());
+        comments.set("GENRE", &meta.genre);
+        if meta.date != 0 {
+            comments.set("DATE", &meta.date.to_string());
+        } else {
+            comments.remove("DATE");
+        }
+
+        comments.remove_if(is_track_key);
+        for (track, number) in meta.tracks.iter().zip(numbers) {
+            comments.set(&track_key(number, "TITLE"), &track.title);
+            comments.set(&track_key(number, "ARTIST"), &track.artist);
+            if let Some(isrc) = &track.isrc {
+                comments.set(&track_key(number, "ISRC"), isrc);
+            }
+            comments.set(
+                &track_key(number, "TITLESORT"),
+                track.title_sort.as_deref().unwrap_or_default(),
+            );
+            comments.set(
+                &track_key(number, "ARTISTSORT"),
+                track.artist_sort.as_deref().unwrap_or_default(),
+            );
+        }
+
+        flac.set_comments(&comments);
+        flac.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fileio::VorbisComment, info_struct::Track};
+
+    /// FLAC image of 3 minutes of 44.1 kHz audio with `comments`, and CUESHEET block
+    /// `cuesheet` if any.
+    fn image(name: &str, comments: &[(&str, &str)], cuesheet: Option<Vec<u8>>) -> PathBuf {
+        let samples: u64 = 44100 * 180;
+        let mut info = vec![0u8; 34];
+        info[10..18].copy_from_slice(&(44100 << 44 | 1 << 41 | 15 << 36 | samples).to_be_bytes());
+        let mut blocks = vec![(0u8, info)];
+        if let Some(cuesheet) = cuesheet {
+            blocks.push((5, cuesheet));
+        }
+
+        let mut data = b"fLaC".to_vec();
+        for (i, (kind, block)) in blocks.iter().enumerate() {
+            let last = if i + 1 == blocks.len() { 0x80 } else { 0 };
+            data.push(kind | last);
+            data.extend(&(block.len() as u32).to_be_bytes()[1..]);
+            data.extend(block);
+        }
+        data.extend(b"audio");
+        let path = std::env::temp_dir().join(format!("music_info_{}_{}", std::process::id(), name));
+        std::fs::write(&path, data).unwrap();
+
+        let mut flac = FlacFile::open(&path).unwrap();
+        flac.set_comments(&VorbisComment {
+            vendor: "test".into(),
+            comments: comments
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        });
+        flac.save().unwrap();
+        path
+    }
+
+    const SHEET: &str = "FILE \"image.flac\" WAVE
+  TRACK 05 AUDIO
+    INDEX 01 00:00:00
+  TRACK 06 AUDIO
+    INDEX 01 01:00:00
+";
+
+    #[test]
+    fn track_keys_follow_sheet_numbers() {
+        let path = image(
+            "cue_image_keys.flac",
+            &[
+                ("CUESHEET", SHEET),
+                ("COMMENT", "keep"),
+                ("TRACK09_TITLE", "stale"),
+            ],
+            None,
+        );
+        let img = CueImage::new(&path, None);
+
+        let mut meta = img.read().unwrap();
+        meta.tracks = vec![Track::new("Five", "A"), Track::new("Six", "B")];
+        img.write(&meta).unwrap();
+
+        let comments = FlacFile::open(&path).unwrap().comments().unwrap();
+        let result = img.read().unwrap();
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(comments.get("TRACK05_TITLE"), Some("Five"));
+        assert_eq!(comments.get("TRACK06_ARTIST"), Some("B"));
+        assert_eq!(comments.get("TRACK01_TITLE"), None);
+        assert_eq!(comments.get("TRACK09_TITLE"), None);
+        assert_eq!(comments.get("COMMENT"), Some("keep"));
+        assert_eq!(result.tracks[0].title, "Five");
+        assert_eq!(result.tracks[1].artist, "B");
+        assert_eq!(result.tracks[1].length, Some(120));
+        assert!(data.ends_with(b"audio"));
+    }
+
+    #[test]
+    fn tracks_are_read_from_cuesheet_block() {
+        // 2 tracks and lead-out, each track with INDEX 01 only
+        let mut cuesheet = vec![0u8; 395];
+        cuesheet.push(3);
+        for (number, offset) in [(1u8, 0u64), (2, 44100 * 60), (170, 44100 * 180)] {
+            cuesheet.extend(offset.to_be_bytes());
+            cuesheet.push(number);
+            cuesheet.extend([0; 26]);
+            if number == 170 {
+                cuesheet.push(0);
+            } else {
+                cuesheet.push(1);
+                cuesheet.extend([0; 8]);
+                cuesheet.extend([1, 0, 0, 0]);
+            }
+        }
+        let path = image(
+            "cue_image_block.flac",
+            &[("TRACK02_TITLE", "Two")],
+            Some(cuesheet),
+        );
+
+        let result = CueImage::new(&path, None).read();
+        std::fs::remove_file(&path).unwrap();
+        let result = result.unwrap();
+
+        assert_eq!(result.tracks.len(), 2);
+        assert_eq!(result.tracks[0].length, Some(60));
+        assert_eq!(result.tracks[1].length, Some(120));
+        assert_eq!(result.tracks[1].title, "Two");
+    }
+}
@@ -0,0 +1,463 @@
+use std::{
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+const STREAMINFO: u8 = 0;
+const PADDING: u8 = 1;
+const VORBIS_COMMENT: u8 = 4;
+const CUESHEET: u8 = 5;
+const PICTURE: u8 = 6;
+
+/// length of STREAMINFO block
+const STREAMINFO_LEN: usize = 34;
+
+/// padding added when metadata does not fit and the file is rewritten
+const NEW_PADDING: usize = 8192;
+
+/// lead-out track number of CD cuesheets
+const LEADOUT_TRACK: u8 = 170;
+
+#[derive(Debug, Clone)]
+struct Block {
+    kind: u8,
+    data: Vec<u8>,
+}
+
+/// metadata blocks of a FLAC file, read and written without taglib
+/// to handle Vorbis comments taglib does not expose and CUESHEET blocks.
+#[derive(Debug, Clone)]
+pub struct FlacFile {
+    path: PathBuf,
+    blocks: Vec<Block>,
+    /// offset of the first audio frame
+    audio_offset: u64,
+}
+
+/// Vorbis comment block, keys are compared case-insensitively.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VorbisComment {
+    pub vendor: String,
+    pub comments: Vec<(String, String)>,
+}
+
+/// track of a CUESHEET block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CueSheetTrack {
+    pub number: u8,
+    /// offset of INDEX 01 in samples from the start of the file
+    pub start: u64,
+    pub isrc: Option<String>,
+}
+
+fn read_u32_le(data: &[u8], pos: &mut usize) -> anyhow::Result<u32> {
+    let bytes = data
+        .get(*pos..*pos + 4)
+        .ok_or(anyhow::anyhow!("Error: broken Vorbis comment"))?;
+    *pos += 4;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_string(data: &[u8], pos: &mut usize) -> anyhow::Result<String> {
+    let len = read_u32_le(data, pos)? as usize;
+    let bytes = data
+        .get(*pos..*pos + len)
+        .ok_or(anyhow::anyhow!("Error: broken Vorbis comment"))?;
+    *pos += len;
+    Ok(String::from_utf8_lossy(bytes).into_owned())
+}
+
+fn read_u64_be(data: &[u8], pos: usize) -> anyhow::Result<u64> {
+    let bytes = data
+        .get(pos..pos + 8)
+        .ok_or(anyhow::anyhow!("Error: broken CUESHEET block"))?;
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(bytes);
+    Ok(u64::from_be_bytes(buf))
+}
+
+impl VorbisComment {
+    pub(crate) fn parse(data: &[u8]) -> anyhow::Result<VorbisComment> {
+        let mut pos = 0;
+        let vendor = read_string(data, &mut pos)?;
+        let count = read_u32_le(data, &mut pos)?;
+
+        let mut comments = Vec::new();
+        for _ in 0..count {
+            let comment = read_string(data, &mut pos)?;
+            if let Some((key, value)) = comment.split_once('=') {
+                comments.push((key.to_string(), value.to_string()));
+            }
+        }
+
+        Ok(VorbisComment { vendor, comments })
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+        result.extend((self.vendor.len() as u32).to_le_bytes());
+        result.extend(self.vendor.as_bytes());
+        result.extend((self.comments.len() as u32).to_le_bytes());
+
+        for (key, value) in &self.comments {
+            let comment = format!("{}={}", key, value);
+            result.extend((comment.len() as u32).to_le_bytes());
+            result.extend(comment.as_bytes());
+        }
+        result
+    }
+
+    /// first value of `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.comments
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// replace all values of `key`, or remove it if `value` is empty.
+    pub fn set(&mut self, key: &str, value: &str) {
+        self.remove(key);
+        if !value.is_empty() {
+            self.comments.push((key.to_uppercase(), value.to_string()));
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.comments.retain(|(k, _)| !k.eq_ignore_ascii_case(key));
+    }
+
+    /// remove all comments whose upper-cased key matches `pred`.
+    pub fn remove_if<F: Fn(&str) -> bool>(&mut self, pred: F) {
+        self.comments.retain(|(k, _)| !pred(&k.to_uppercase()));
+    }
+}
+
+impl FlacFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> anyhow::Result<FlacFile> {
+        let path = path.as_ref();
+        let mut file = std::fs::File::open(path)
+            .map_err(|e| anyhow::anyhow!("Error: could not open {}: {}", path.display(), e))?;
+
+        let mut magic = [0u8; 4];
+        file.read_exact(&mut magic)?;
+        if &magic != b"fLaC" {
+            anyhow::bail!("Error: {} is not a FLAC file", path.display())
+        }
+
+        let mut blocks = Vec::new();
+        let mut offset = 4;
+        loop {
+            let mut header = [0u8; 4];
+            file.read_exact(&mut header)?;
+            let last = header[0] & 0x80 != 0;
+            let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+
+            let mut data = vec![0u8; len];
+            file.read_exact(&mut data)?;
+            offset += 4 + len as u64;
+
+            blocks.push(Block {
+                kind: header[0] & 0x7F,
+                data,
+            });
+            if last {
+                break;
+            }
+        }
+
+        match blocks.first() {
+            Some(b) if b.kind == STREAMINFO && b.data.len() >= STREAMINFO_LEN => (),
+            Some(b) if b.kind == STREAMINFO => {
+                anyhow::bail!("Error: broken STREAMINFO in {}", path.display())
+            }
+            _ => anyhow::bail!("Error: no STREAMINFO in {}", path.display()),
+        }
+
+        Ok(FlacFile {
+            path: path.into(),
+            blocks,
+            audio_offset: offset,
+        })
+    }
+
+    /// (sample rate, total samples) from STREAMINFO.
+    pub fn stream_info(&self) -> (u32, u64) {
+        let info = &self.blocks[0].data;
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(&info[10..18]);
+        let x = u64::from_be_bytes(buf);
+        ((x >> 44) as u32, x & 0xF_FFFF_FFFF)
+    }
+
+    pub fn comments(&self) -> anyhow::Result<VorbisComment> {
+        match self.blocks.iter().find(|b| b.kind == VORBIS_COMMENT) {
+            Some(block) => VorbisComment::parse(&block.data),
+            None => Ok(VorbisComment::default()),
+        }
+    }
+
+    pub fn set_comments(&mut self, comments: &VorbisComment) {
+        let data = comments.to_bytes();
+        match self.blocks.iter_mut().find(|b| b.kind == VORBIS_COMMENT) {
+            Some(block) => block.data = data,
+            None => self.blocks.insert(
+                1,
+                Block {
+                    kind: VORBIS_COMMENT,
+                    data,
+                },
+            ),
+        }
+    }
+
+    /// true if there is any PICTURE block.
+    pub fn has_pictures(&self) -> bool {
+        self.blocks.iter().any(|b| b.kind == PICTURE)
+    }
+
+    pub fn remove_pictures(&mut self) {
+        self.blocks.retain(|b| b.kind != PICTURE);
+    }
+
+    /// tracks of CUESHEET block, without lead-out. None if there is no CUESHEET block.
+    pub fn cuesheet(&self) -> anyhow::Result<Option<Vec<CueSheetTrack>>> {
+        let data = match self.blocks.iter().find(|b| b.kind == CUESHEET) {
+            Some(block) => &block.data,
+            None => return Ok(None),
+        };
+
+        // catalog (128), lead-in (8), flags and reserved (1 + 258)
+        let count = *data
+            .get(395)
+            .ok_or(anyhow::anyhow!("Error: broken CUESHEET block"))?;
+        let mut pos = 396;
+        let mut tracks = Vec::new();
+
+        for _ in 0..count {
+            if data.len() < pos + 36 {
+                anyhow::bail!("Error: broken CUESHEET block")
+            }
+            let offset = read_u64_be(data, pos)?;
+            let number = data[pos + 8];
+            let isrc: String = data[pos + 9..pos + 21]
+                .iter()
+                .take_while(|b| **b != 0)
+                .map(|b| *b as char)
+                .collect();
+            let indices = data[pos + 35] as usize;
+            pos += 36;
+
+            let mut start = offset;
+            for i in 0..indices {
+                let index_offset = read_u64_be(data, pos + i * 12)?;
+                if data[pos + i * 12 + 8] == 1 {
+                    start = offset + index_offset;
+                }
+            }
+            pos += indices * 12;
+
+            if number != LEADOUT_TRACK {
+                tracks.push(CueSheetTrack {
+                    number,
+                    start,
+                    isrc: (!isrc.is_empty()).then_some(isrc),
+                });
+            }
+        }
+
+        Ok(Some(tracks))
+    }
+
+    fn metadata_bytes(blocks: &[Block]) -> Vec<u8> {
+        let mut result = Vec::new();
+        for (i, block) in blocks.iter().enumerate() {
+            let last = if i + 1 == blocks.len() { 0x80 } else { 0 };
+            let len = (block.data.len() as u32).to_be_bytes();
+            result.push(block.kind | last);
+            result.extend(&len[1..]);
+            result.extend(&block.data);
+        }
+        result
+    }
+
+    /// write metadata blocks, in place if they fit in the space of old ones with padding.
+    pub fn save(&self) -> anyhow::Result<()> {
+        let mut blocks: Vec<_> = self
+            .blocks
+            .iter()
+            .filter(|b| b.kind != PADDING)
+            .cloned()
+            .collect();
+        let available = self.audio_offset as usize - 4;
+        let needed = FlacFile::metadata_bytes(&blocks).len();
+
+        if needed == available || needed + 4 <= available {
+            if needed < available {
+                blocks.push(Block {
+                    kind: PADDING,
+                    data: vec![0; available - needed - 4],
+                });
+            }
+            let mut file = std::fs::OpenOptions::new().write(true).open(&self.path)?;
+            file.seek(SeekFrom::Start(4))?;
+            file.write_all(&FlacFile::metadata_bytes(&blocks))?;
+            return Ok(());
+        }
+
+        // metadata grew, rewrite whole file through a temporary file
+        blocks.push(Block {
+            kind: PADDING,
+            data: vec![0; NEW_PADDING],
+        });
+        let tmp = self.path.with_extension("flac.tmp");
+        {
+            let mut src = std::fs::File::open(&self.path)?;
+            src.seek(SeekFrom::Start(self.audio_offset))?;
+            let mut dst = std::io::BufWriter::new(std::fs::File::create(&tmp)?);
+            dst.write_all(b"fLaC")?;
+            dst.write_all(&FlacFile::metadata_bytes(&blocks))?;
+            std::io::copy(&mut src, &mut dst)?;
+            dst.flush()?;
+        }
+        std::fs::rename(tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// STREAMINFO of 44.1 kHz stereo 16 bit audio of `samples` samples.
+    fn stream_info(samples: u64) -> Vec<u8> {
+        let mut data = vec![0u8; STREAMINFO_LEN];
+        let x = 44100 << 44 | 1 << 41 | 15 << 36 | samples;
+        data[10..18].copy_from_slice(&x.to_be_bytes());
+        data
+    }
+
+    /// FLAC file of `blocks` followed by `audio`.
+    fn flac_file(name: &str, blocks: &[Block], audio: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("music_info_{}_{}", std::process::id(), name));
+        let data = [b"fLaC".as_slice(), &FlacFile::metadata_bytes(blocks), audio].concat();
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    fn block(kind: u8, data: Vec<u8>) -> Block {
+        Block { kind, data }
+    }
+
+    fn comments(pairs: &[(&str, &str)]) -> VorbisComment {
+        VorbisComment {
+            vendor: "test".into(),
+            comments: pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn saves_in_place_when_comments_fit() {
+        let blocks = [
+            block(STREAMINFO, stream_info(44100)),
+            block(VORBIS_COMMENT, comments(&[("TITLE", "Old")]).to_bytes()),
+            block(PADDING, vec![0; 100]),
+        ];
+        let path = flac_file("flac_in_place.flac", &blocks, b"audio");
+        let len = std::fs::metadata(&path).unwrap().len();
+
+        let mut flac = FlacFile::open(&path).unwrap();
+        flac.set_comments(&comments(&[("TITLE", "New"), ("ARTIST", "Someone")]));
+        flac.save().unwrap();
+
+        let result = FlacFile::open(&path).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(data.len() as u64, len);
+        assert!(data.ends_with(b"audio"));
+        assert_eq!(result.comments().unwrap().get("title"), Some("New"));
+        assert_eq!(result.comments().unwrap().get("ARTIST"), Some("Someone"));
+        assert_eq!(result.stream_info(), (44100, 44100));
+    }
+
+    #[test]
+    fn rewrites_file_when_comments_grow() {
+        let blocks = [block(STREAMINFO, stream_info(0))];
+        let path = flac_file("flac_grow.flac", &blocks, b"audio");
+        let long = "x".repeat(1000);
+
+        let mut flac = FlacFile::open(&path).unwrap();
+        flac.set_comments(&comments(&[("COMMENT", &long)]));
+        flac.save().unwrap();
+
+        let result = FlacFile::open(&path).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(data.ends_with(b"audio"));
+        assert_eq!(
+            result.comments().unwrap().get("COMMENT"),
+            Some(long.as_str())
+        );
+        // new padding for later writes in place
+        let padding = result.blocks.iter().find(|b| b.kind == PADDING).unwrap();
+        assert_eq!(padding.data.len(), NEW_PADDING);
+    }
+
+    #[test]
+    fn reads_cuesheet_block() {
+        let mut data = vec![0u8; 395];
+        data.push(3);
+        // track number, offset, ISRC and INDEX 00/01 offsets
+        for (number, offset, isrc, indices) in [
+            (1u8, 0u64, *b"JPXX00100001", [0u64, 0].as_slice()),
+            (2, 44100 * 60, [0; 12], &[0, 588 * 100]),
+            (LEADOUT_TRACK, 44100 * 120, [0; 12], &[]),
+        ] {
+            data.extend(offset.to_be_bytes());
+            data.push(number);
+            data.extend(isrc);
+            data.extend([0; 14]);
+            data.push(indices.len() as u8);
+            for (i, index) in indices.iter().enumerate() {
+                data.extend(index.to_be_bytes());
+                data.extend([i as u8, 0, 0, 0]);
+            }
+        }
+        let blocks = [block(STREAMINFO, stream_info(0)), block(CUESHEET, data)];
+        let path = flac_file("flac_cuesheet.flac", &blocks, b"");
+
+        let result = FlacFile::open(&path).unwrap().cuesheet().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            result,
+            Some(vec![
+                CueSheetTrack {
+                    number: 1,
+                    start: 0,
+                    isrc: Some("JPXX00100001".into()),
+                },
+                CueSheetTrack {
+                    number: 2,
+                    start: 44100 * 60 + 588 * 100,
+                    isrc: None,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn truncated_stream_info_is_rejected() {
+        let blocks = [block(STREAMINFO, vec![0; 12])];
+        let path = flac_file("flac_truncated.flac", &blocks, b"");
+
+        let result = FlacFile::open(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,306 @@
+use std::{
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+/// padding added when a tag does not fit and the file is rewritten
+const NEW_PADDING: usize = 1024;
+
+/// text of frame `id` in the ID3v2 tag at the start of file at `path`, like `TPOS`.
+/// ID3v2.2 frames are found by their 3 character IDs, e.g. `TPA`.
+pub fn read_text<P: AsRef<Path>>(path: P, id: &str) -> anyhow::Result<Option<String>> {
+    let path = path.as_ref();
+    let mut file = std::fs::File::open(path)
+        .map_err(|e| anyhow::anyhow!("Error: could not open {}: {}", path.display(), e))?;
+
+    let mut header = [0u8; 10];
+    file.read_exact(&mut header)?;
+    if &header[..3] != b"ID3" {
+        return Ok(None);
+    }
+
+    let mut tag = vec![0u8; syncsafe(&header[6..10])];
+    file.read_exact(&mut tag)?;
+    Ok(find_text(&tag, header[3], header[5], id))
+}
+
+/// set text frames `frames` in the ID3v2 tag of file at `path`, removing those with None.
+/// a v2.4 tag is added if the file has none. v2.2 tags and unsynchronised tags are not
+/// supported.
+pub fn write_texts<P: AsRef<Path>>(path: P, frames: &[(&str, Option<&str>)]) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    let mut file = std::fs::File::open(path)
+        .map_err(|e| anyhow::anyhow!("Error: could not open {}: {}", path.display(), e))?;
+
+    let mut header = [0u8; 10];
+    let has_tag = file.read_exact(&mut header).is_ok() && &header[..3] == b"ID3";
+    let (version, flags, tag) = if has_tag {
+        let mut tag = vec![0u8; syncsafe(&header[6..10])];
+        file.read_exact(&mut tag)?;
+        (header[3], header[5], tag)
+    } else {
+        (4, 0, Vec::new())
+    };
+    if version < 3 || flags & 0x80 != 0 || flags & 0x10 != 0 {
+        anyhow::bail!(
+            "Error: ID3v2.{} tag of {} is not supported",
+            version,
+            path.display()
+        )
+    }
+
+    let (extended, mut current) = parse_frames(&tag, version, flags).ok_or(anyhow::anyhow!(
+        "Error: broken ID3v2 tag in {}",
+        path.display()
+    ))?;
+    for (id, value) in frames {
+        current.retain(|f| f.id != *id);
+        if let Some(value) = value {
+            current.push(Frame {
+                id: id.to_string(),
+                flags: [0, 0],
+                body: encode_text(value, version),
+            });
+        }
+    }
+
+    let mut body = tag[..extended].to_vec();
+    for frame in &current {
+        body.extend(frame.id.as_bytes());
+        match version {
+            3 => body.extend((frame.body.len() as u32).to_be_bytes()),
+            _ => body.extend(to_syncsafe(frame.body.len())),
+        }
+        body.extend(frame.flags);
+        body.extend(&frame.body);
+    }
+
+    let old_len = tag.len();
+    let new_header = |size: usize| {
+        let mut result = vec![b'I', b'D', b'3', version, 0, flags];
+        result.extend(to_syncsafe(size));
+        result
+    };
+
+    if has_tag && body.len() <= old_len {
+        // fits in the old tag, overwrite it with padding
+        body.resize(old_len, 0);
+        let mut file = std::fs::OpenOptions::new().write(true).open(path)?;
+        file.write_all(&new_header(old_len))?;
+        file.write_all(&body)?;
+        return Ok(());
+    }
+
+    body.resize(body.len() + NEW_PADDING, 0);
+    let audio_offset = if has_tag { 10 + old_len as u64 } else { 0 };
+    let tmp = path.with_extension("mp3.tmp");
+    {
+        file.seek(SeekFrom::Start(audio_offset))?;
+        let mut dst = std::io::BufWriter::new(std::fs::File::create(&tmp)?);
+        dst.write_all(&new_header(body.len()))?;
+        dst.write_all(&body)?;
+        std::io::copy(&mut file, &mut dst)?;
+        dst.flush()?;
+    }
+    std::fs::rename(tmp, path)?;
+    Ok(())
+}
+
+fn syncsafe(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0, |acc, b| acc << 7 | (*b & 0x7F) as usize)
+}
+
+fn to_syncsafe(size: usize) -> [u8; 4] {
+    [3, 2, 1, 0].map(|i| (size >> (7 * i) & 0x7F) as u8)
+}
+
+fn big_endian(bytes: &[u8]) -> usize {
+    bytes.iter().fold(0, |acc, b| acc << 8 | *b as usize)
+}
+
+/// frame of a tag, with flags and body kept as they are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Frame {
+    id: String,
+    flags: [u8; 2],
+    body: Vec<u8>,
+}
+
+/// length of extended header and frames of `tag`, the tag body after its header.
+/// frames of ID3v2.2 have no flags.
+fn parse_frames(tag: &[u8], version: u8, flags: u8) -> Option<(usize, Vec<Frame>)> {
+    let (id_len, header_len) = if version == 2 { (3, 6) } else { (4, 10) };
+
+    let mut pos = 0;
+    if flags & 0x40 != 0 && version >= 3 {
+        // extended header, its size excludes itself in v2.3
+        let size = tag.get(0..4)?;
+        pos = match version {
+            3 => big_endian(size) + 4,
+            _ => syncsafe(size),
+        };
+    }
+    let extended = pos;
+
+    let mut frames = Vec::new();
+    while pos + header_len <= tag.len() {
+        let frame = &tag[pos..pos + header_len];
+        if frame[0] == 0 {
+            // padding
+            break;
+        }
+        let size = match version {
+            2 => big_endian(&frame[3..6]),
+            3 => big_endian(&frame[4..8]),
+            _ => syncsafe(&frame[4..8]),
+        };
+        let body = tag.get(pos + header_len..pos + header_len + size)?;
+        frames.push(Frame {
+            id: String::from_utf8_lossy(&frame[..id_len]).into_owned(),
+            flags: if version == 2 {
+                [0, 0]
+            } else {
+                [frame[8], frame[9]]
+            },
+            body: body.to_vec(),
+        });
+        pos += header_len + size;
+    }
+    Some((extended, frames))
+}
+
+/// text of frame `id` in `tag`, the tag body after its header.
+fn find_text(tag: &[u8], version: u8, flags: u8, id: &str) -> Option<String> {
+    let (_, frames) = parse_frames(tag, version, flags)?;
+    frames
+        .iter()
+        .find(|f| f.id == id)
+        .and_then(|f| decode_text(&f.body))
+}
+
+/// text frame body of `value`, in UTF-8 for v2.4 and UTF-16 for v2.3.
+fn encode_text(value: &str, version: u8) -> Vec<u8> {
+    match version {
+        3 => {
+            let mut result = vec![1, 0xFF, 0xFE];
+            for unit in value.encode_utf16() {
+                result.extend(unit.to_le_bytes());
+            }
+            result
+        }
+        _ => [&[3], value.as_bytes()].concat(),
+    }
+}
+
+/// text frame body: encoding byte, then text terminated or separated by null.
+fn decode_text(body: &[u8]) -> Option<String> {
+    let (encoding, text) = body.split_first()?;
+    let text = match encoding {
+        1 | 2 => {
+            let mut units: Vec<u16> = text
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect();
+            match units.first() {
+                Some(0xFFFE) => {
+                    units = units.iter().skip(1).map(|u| u.swap_bytes()).collect();
+                }
+                Some(0xFEFF) => {
+                    units.remove(0);
+                }
+                _ => (),
+            }
+            String::from_utf16_lossy(&units)
+        }
+        3 => String::from_utf8_lossy(text).into_owned(),
+        // ISO-8859-1
+        _ => text.iter().map(|b| *b as char).collect(),
+    };
+    text.split('\0').next().map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(id: &str, body: &[u8]) -> Vec<u8> {
+        let mut result = id.as_bytes().to_vec();
+        result.extend((body.len() as u32).to_be_bytes());
+        result.extend([0, 0]);
+        result.extend(body);
+        result
+    }
+
+    #[test]
+    fn finds_text_frame_in_v3() {
+        let mut tag = frame("TIT2", b"\x00Title");
+        tag.extend(frame("TPOS", b"\x002/3\x00"));
+        tag.extend([0; 16]);
+        assert_eq!(find_text(&tag, 3, 0, "TPOS").as_deref(), Some("2/3"));
+        assert_eq!(find_text(&tag, 3, 0, "TRCK"), None);
+    }
+
+    #[test]
+    fn decodes_utf16_with_bom() {
+        let tag = frame("TPOS", b"\x01\xFF\xFE1\x00/\x002\x00");
+        assert_eq!(find_text(&tag, 3, 0, "TPOS").as_deref(), Some("1/2"));
+    }
+
+    #[test]
+    fn reads_v2_frames() {
+        let tag = [b"TPA\x00\x00\x02\x002".as_slice(), &[0; 6]].concat();
+        assert_eq!(find_text(&tag, 2, 0, "TPA").as_deref(), Some("2"));
+    }
+
+    fn temp_file(name: &str, data: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("music_info_{}_{}", std::process::id(), name));
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn adds_tag_to_file_without_one() {
+        let path = temp_file("id3_new.mp3", b"\xFF\xFBaudio");
+
+        write_texts(&path, &[("TSOA", Some("Album, The"))]).unwrap();
+        assert_eq!(
+            read_text(&path, "TSOA").unwrap().as_deref(),
+            Some("Album, The")
+        );
+        let len = std::fs::metadata(&path).unwrap().len();
+
+        // smaller tags are written in place
+        write_texts(&path, &[("TSOA", None), ("TSOP", Some("Artist"))]).unwrap();
+        assert_eq!(read_text(&path, "TSOA").unwrap(), None);
+        assert_eq!(read_text(&path, "TSOP").unwrap().as_deref(), Some("Artist"));
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(data.ends_with(b"\xFF\xFBaudio"));
+    }
+
+    #[test]
+    fn keeps_other_frames_of_v3_tag() {
+        let mut tag = frame("TIT2", b"\x00T\xEFtle");
+        tag.extend([0; 4]);
+        let mut data = b"ID3\x03\x00\x00".to_vec();
+        data.extend(to_syncsafe(tag.len()));
+        data.extend(tag);
+        data.extend(b"audio");
+        let path = temp_file("id3_v3.mp3", &data);
+
+        write_texts(&path, &[("TSOT", Some("タイトル"))]).unwrap();
+        let title = read_text(&path, "TIT2").unwrap();
+        let sort = read_text(&path, "TSOT").unwrap();
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(title.as_deref(), Some("T\u{EF}tle"));
+        assert_eq!(sort.as_deref(), Some("タイトル"));
+        assert_eq!(data[3], 3);
+        assert!(data.ends_with(b"audio"));
+    }
+}
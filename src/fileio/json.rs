@@ -1,7 +1,13 @@
 use std::path::{Path, PathBuf};
 
-use crate::{info_struct::Metadata, traits::MetaFileIO};
+use crate::{
+    info_struct::Metadata,
+    patch::MetaPatch,
+    schema::{self, Versioned},
+    traits::MetaFileIO,
+};
 
+/// metadata document in JSON, written with the schema version and migrated on read.
 pub struct Json {
     path: PathBuf,
 }
@@ -14,21 +20,25 @@ impl Json {
     }
 
     pub fn to_string(meta: &Metadata) -> anyhow::Result<String> {
-        let result = serde_json::to_string_pretty(meta)?;
+        let result = serde_json::to_string_pretty(&Versioned::new(meta))?;
         Ok(result)
     }
+
+    /// read as a patch, where missing or blank fields are kept and null fields are cleared.
+    pub fn read_patch(&self) -> anyhow::Result<MetaPatch> {
+        let json_str = std::fs::read_to_string(&self.path)?;
+        schema::from_json(&json_str, &self.path)
+    }
 }
 
 impl MetaFileIO for Json {
     fn read(&self) -> anyhow::Result<Metadata> {
         let json_str = std::fs::read_to_string(&self.path)?;
-        let result = serde_json::from_str(&json_str)?;
-        Ok(result)
+        schema::from_json(&json_str, &self.path)
     }
 
     fn write(&self, meta: &Metadata) -> anyhow::Result<()> {
-        let json_str = serde_json::to_string_pretty(meta)?;
-        std::fs::write(&self.path, json_str)?;
+        std::fs::write(&self.path, Json::to_string(meta)?)?;
         Ok(())
     }
 }
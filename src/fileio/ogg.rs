@@ -0,0 +1,367 @@
+use std::{
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::fileio::flac::VorbisComment;
+
+/// header type flag of pages starting with the rest of a packet
+const CONTINUED: u8 = 0x01;
+
+/// page of an Ogg stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Page {
+    header_type: u8,
+    granule: u64,
+    serial: u32,
+    sequence: u32,
+    /// lacing values, a value shorter than 255 ends a packet
+    segments: Vec<u8>,
+    data: Vec<u8>,
+}
+
+/// CRC-32 of Ogg pages: polynomial 0x04C11DB7, no reflection, initial value 0.
+fn crc32(data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        let mut r = (i as u32) << 24;
+        for _ in 0..8 {
+            r = if r & 0x8000_0000 != 0 {
+                r << 1 ^ 0x04C1_1DB7
+            } else {
+                r << 1
+            };
+        }
+        *entry = r;
+    }
+    data.iter().fold(0, |crc, b| {
+        crc << 8 ^ table[((crc >> 24) as u8 ^ b) as usize]
+    })
+}
+
+impl Page {
+    /// next page of `reader`, None at the end of stream.
+    fn read<R: Read>(reader: &mut R) -> anyhow::Result<Option<Page>> {
+        let mut header = [0u8; 27];
+        match reader.read_exact(&mut header) {
+            Ok(()) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        if &header[..4] != b"OggS" {
+            anyhow::bail!("Error: broken Ogg page")
+        }
+
+        let mut segments = vec![0u8; header[26] as usize];
+        reader.read_exact(&mut segments)?;
+        let mut data = vec![0u8; segments.iter().map(|s| *s as usize).sum()];
+        reader.read_exact(&mut data)?;
+
+        let le32 =
+            |i: usize| u32::from_le_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+        let mut granule = [0u8; 8];
+        granule.copy_from_slice(&header[6..14]);
+        Ok(Some(Page {
+            header_type: header[5],
+            granule: u64::from_le_bytes(granule),
+            serial: le32(14),
+            sequence: le32(18),
+            segments,
+            data,
+        }))
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut result = b"OggS\0".to_vec();
+        result.push(self.header_type);
+        result.extend(self.granule.to_le_bytes());
+        result.extend(self.serial.to_le_bytes());
+        result.extend(self.sequence.to_le_bytes());
+        result.extend([0; 4]);
+        result.push(self.segments.len() as u8);
+        result.extend(&self.segments);
+        result.extend(&self.data);
+
+        let crc = crc32(&result).to_le_bytes();
+        result[22..26].copy_from_slice(&crc);
+        result
+    }
+
+    /// packets split into segments of their lengths.
+    fn packets(&self) -> Vec<(&[u8], bool)> {
+        let mut result = Vec::new();
+        let mut start = 0;
+        let mut pos = 0;
+        for len in &self.segments {
+            pos += *len as usize;
+            if *len < 255 {
+                result.push((&self.data[start..pos], true));
+                start = pos;
+            }
+        }
+        if start < pos {
+            result.push((&self.data[start..pos], false));
+        }
+        result
+    }
+}
+
+/// pages holding `packets` of stream `serial`, numbered from `sequence`.
+fn paginate(packets: &[Vec<u8>], serial: u32, sequence: u32) -> Vec<Page> {
+    let mut pages = Vec::new();
+    let mut page = Page {
+        header_type: 0,
+        granule: 0,
+        serial,
+        sequence,
+        segments: Vec::new(),
+        data: Vec::new(),
+    };
+    // true if a packet ends on the current page
+    let mut complete = false;
+
+    for packet in packets {
+        let mut lacing = vec![255u8; packet.len() / 255];
+        lacing.push((packet.len() % 255) as u8);
+
+        let mut pos = 0;
+        for (i, len) in lacing.iter().enumerate() {
+            if page.segments.len() == 255 {
+                // no packet ending on a page is marked by granule position -1
+                let continued = i > 0;
+                let next = Page {
+                    header_type: if continued { CONTINUED } else { 0 },
+                    sequence: page.sequence + 1,
+                    segments: Vec::new(),
+                    data: Vec::new(),
+                    ..page.clone()
+                };
+                if !complete {
+                    page.granule = u64::MAX;
+                }
+                pages.push(std::mem::replace(&mut page, next));
+                complete = false;
+            }
+            page.segments.push(*len);
+            page.data.extend(&packet[pos..pos + *len as usize]);
+            pos += *len as usize;
+        }
+        complete = true;
+    }
+    pages.push(page);
+    pages
+}
+
+/// comment header of an Ogg Vorbis or Opus file at `path`.
+pub fn read_comments<P: AsRef<Path>>(path: P) -> anyhow::Result<VorbisComment> {
+    let path = path.as_ref();
+    let file = std::fs::File::open(path)
+        .map_err(|e| anyhow::anyhow!("Error: could not open {}: {}", path.display(), e))?;
+    parse_comments(BufReader::new(file))
+}
+
+/// header packets of the first logical stream and the pages holding them:
+/// identification, comment and, for Vorbis, setup headers.
+fn header_packets<R: Read>(reader: &mut R) -> anyhow::Result<(Vec<Vec<u8>>, Vec<Page>)> {
+    let mut packets = Vec::new();
+    let mut packet = Vec::new();
+    let mut pages: Vec<Page> = Vec::new();
+    let mut count = 2;
+
+    while packets.len() < count {
+        let page =
+            Page::read(reader)?.ok_or(anyhow::anyhow!("Error: Ogg stream ends in headers"))?;
+        if matches!(pages.first(), Some(first) if first.serial != page.serial) {
+            // page of another stream
+            continue;
+        }
+        for (data, complete) in page.packets() {
+            packet.extend(data);
+            if complete {
+                if packets.is_empty() && packet.starts_with(b"\x01vorbis") {
+                    count = 3;
+                }
+                packets.push(std::mem::take(&mut packet));
+            }
+        }
+        pages.push(page);
+    }
+    if packets.len() > count || !packet.is_empty() {
+        anyhow::bail!("Error: audio data shares a page with Ogg headers")
+    }
+
+    Ok((packets, pages))
+}
+
+fn parse_comments<R: Read>(mut reader: R) -> anyhow::Result<VorbisComment> {
+    let (packets, _) = header_packets(&mut reader)?;
+    let comment = &packets[1];
+
+    if let Some(data) = comment.strip_prefix(b"\x03vorbis") {
+        VorbisComment::parse(data)
+    } else if let Some(data) = comment.strip_prefix(b"OpusTags") {
+        VorbisComment::parse(data)
+    } else {
+        anyhow::bail!("Error: no Vorbis comment found in Ogg stream")
+    }
+}
+
+/// replace the comment header of an Ogg Vorbis or Opus file at `path`.
+/// header pages are rebuilt, and later pages renumbered if their count changes.
+pub fn write_comments<P: AsRef<Path>>(path: P, comments: &VorbisComment) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    let file = std::fs::File::open(path)
+        .map_err(|e| anyhow::anyhow!("Error: could not open {}: {}", path.display(), e))?;
+    let mut reader = BufReader::new(file);
+
+    let (mut packets, pages) = header_packets(&mut reader)?;
+    packets[1] = if packets[0].starts_with(b"\x01vorbis") {
+        // framing bit
+        [b"\x03vorbis".as_slice(), &comments.to_bytes(), &[1]].concat()
+    } else if packets[0].starts_with(b"OpusHead") {
+        [b"OpusTags".as_slice(), &comments.to_bytes()].concat()
+    } else {
+        anyhow::bail!(
+            "Error: {} is not an Ogg Vorbis or Opus file",
+            path.display()
+        )
+    };
+
+    // the identification header is alone on the first page
+    let first = &pages[0];
+    if first.packets().len() != 1 {
+        anyhow::bail!("Error: broken Ogg headers in {}", path.display())
+    }
+    let headers = paginate(&packets[1..], first.serial, first.sequence + 1);
+    let shift = headers.len() as i64 - (pages.len() as i64 - 1);
+
+    let tmp = path.with_extension("ogg.tmp");
+    {
+        let mut dst = BufWriter::new(std::fs::File::create(&tmp)?);
+        dst.write_all(&first.to_bytes())?;
+        for page in &headers {
+            dst.write_all(&page.to_bytes())?;
+        }
+        while let Some(mut page) = Page::read(&mut reader)? {
+            if page.serial == first.serial {
+                page.sequence = (page.sequence as i64 + shift) as u32;
+            }
+            dst.write_all(&page.to_bytes())?;
+        }
+        dst.flush()?;
+    }
+    std::fs::rename(tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// page with one segment for each of `segments`, at most 255 bytes each.
+    fn page(segments: &[&[u8]]) -> Vec<u8> {
+        let mut result = b"OggS".to_vec();
+        result.extend([0; 22]);
+        result.push(segments.len() as u8);
+        result.extend(segments.iter().map(|s| s.len() as u8));
+        for segment in segments {
+            result.extend(*segment);
+        }
+        result
+    }
+
+    fn comment_bytes(comments: &[(&str, &str)]) -> Vec<u8> {
+        let comment = VorbisComment {
+            vendor: "test".into(),
+            comments: comments
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        };
+        comment.to_bytes()
+    }
+
+    #[test]
+    fn reads_vorbis_comment_header() {
+        let comment = [
+            b"\x03vorbis".as_slice(),
+            &comment_bytes(&[("DISCNUMBER", "2")]),
+        ]
+        .concat();
+        let data = [page(&[b"\x01vorbis"]), page(&[&comment, b"\x05vorbis"])].concat();
+
+        let result = parse_comments(data.as_slice()).unwrap();
+        assert_eq!(result.get("discnumber"), Some("2"));
+    }
+
+    #[test]
+    fn reads_opus_tags_spanning_pages() {
+        let title = "x".repeat(300);
+        let comment = [b"OpusTags".as_slice(), &comment_bytes(&[("TITLE", &title)])].concat();
+        // a 255 byte segment continues the packet on the next page
+        let (first, rest) = comment.split_at(255);
+        let data = [page(&[b"OpusHead"]), page(&[first]), page(&[rest])].concat();
+
+        let result = parse_comments(data.as_slice()).unwrap();
+        assert_eq!(result.get("TITLE"), Some(title.as_str()));
+    }
+
+    #[test]
+    fn writes_comments_and_renumbers_pages() {
+        let serial = 7u32.to_le_bytes();
+        let mut data = Vec::new();
+        for (i, segments) in [
+            [b"\x01vorbis".as_slice()].as_slice(),
+            &[
+                &[b"\x03vorbis".as_slice(), &comment_bytes(&[]), &[1]].concat(),
+                b"\x05vorbis",
+            ],
+            &[b"audio"],
+        ]
+        .iter()
+        .enumerate()
+        {
+            let mut p = page(segments);
+            p[14..18].copy_from_slice(&serial);
+            p[18] = i as u8;
+            data.extend(p);
+        }
+        let path = std::env::temp_dir().join(format!(
+            "music_info_{}_{}",
+            std::process::id(),
+            "ogg_write.ogg"
+        ));
+        std::fs::write(&path, data).unwrap();
+
+        // long enough for the comment to take 2 pages
+        let title = "t".repeat(70_000);
+        let mut comments = read_comments(&path).unwrap();
+        comments.set("TITLESORT", &title);
+        write_comments(&path, &comments).unwrap();
+
+        let result = read_comments(&path).unwrap();
+        let mut reader = std::io::BufReader::new(std::fs::File::open(&path).unwrap());
+        let mut pages = Vec::new();
+        while let Some(page) = Page::read(&mut reader).unwrap() {
+            pages.push(page);
+        }
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(result.get("TITLESORT"), Some(title.as_str()));
+        assert_eq!(pages.len(), 4);
+        assert_eq!(pages[1].granule, u64::MAX);
+        assert_eq!(pages[2].header_type, CONTINUED);
+        let audio = pages.last().unwrap();
+        assert_eq!(audio.data, b"audio");
+        assert_eq!(audio.sequence, 3);
+
+        // CRC of the first page, computed with its CRC field zeroed
+        let crc = u32::from_le_bytes([bytes[22], bytes[23], bytes[24], bytes[25]]);
+        let mut first = bytes[..27 + 1 + 7].to_vec();
+        first[22..26].copy_from_slice(&[0; 4]);
+        assert_eq!(crc32(&first), crc);
+        // check value of CRC-32 with polynomial 0x04C11DB7, initial value 0
+        assert_eq!(crc32(b"123456789"), 0x89A1_897F);
+    }
+}
@@ -1,11 +1,191 @@
-use std::{borrow::Borrow, path::Path};
+use std::{
+    borrow::Borrow,
+    path::{Path, PathBuf},
+};
 
+use serde::{Deserialize, Serialize};
 use taglib::{File, FileError};
 
-use crate::{info_struct::*, traits::MetaFileIO};
+use crate::{
+    fileio::{
+        flac::{FlacFile, VorbisComment},
+        id3, ogg,
+    },
+    info_struct::*,
+    patch::{MetaPatch, Patch, TrackPatch},
+    traits::MetaFileIO,
+};
+
+/// difference of track length in seconds reported on write
+const LENGTH_TOLERANCE: u32 = 3;
+
+/// Vorbis comments of sort names, which taglib does not expose
+const ALBUM_SORT: &str = "ALBUMSORT";
+const TITLE_SORT: &str = "TITLESORT";
+const ARTIST_SORT: &str = "ARTISTSORT";
+
+/// ID3v2 frames of sort names by Vorbis comment
+const SORT_FRAMES: [(&str, &str); 3] = [
+    (ALBUM_SORT, "TSOA"),
+    (TITLE_SORT, "TSOT"),
+    (ARTIST_SORT, "TSOP"),
+];
+
+/// disc number in Vorbis comments and ID3v2, which taglib does not expose
+const DISC_NUMBER: &str = "DISCNUMBER";
+const DISC_FRAME: &str = "TPOS";
 
 pub struct TagLib {
     files: Vec<Option<File>>,
+    paths: Vec<Option<PathBuf>>,
+    codecs: Vec<String>,
+}
+
+/// all tags of a single file which taglib can write, and sort names of FLAC, MP3 and Ogg
+/// files, used to restore a file as it was.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq, Eq)]
+pub struct RawTags {
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub comment: String,
+    pub genre: String,
+    pub year: u32,
+    pub track: u32,
+    /// sort names by Vorbis comment key, None if not recorded or not supported
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort_names: Option<Vec<(String, String)>>,
+}
+
+impl RawTags {
+    pub fn read<P: AsRef<Path>>(path: P) -> anyhow::Result<RawTags> {
+        let file = File::new(path.as_ref())
+            .map_err(|_| anyhow::anyhow!("Error: could not open {}", path.as_ref().display()))?;
+        let tag = file
+            .tag()
+            .map_err(|_| anyhow::anyhow!("Error: no available tag found."))?;
+
+        Ok(RawTags {
+            title: tag.title().unwrap_or_default(),
+            artist: tag.artist().unwrap_or_default(),
+            album: tag.album().unwrap_or_default(),
+            comment: tag.comment().unwrap_or_default(),
+            genre: tag.genre().unwrap_or_default(),
+            year: tag.year().unwrap_or_default(),
+            track: tag.track().unwrap_or_default(),
+            sort_names: read_sort_names(path.as_ref()),
+        })
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        let file = File::new(path.as_ref())
+            .map_err(|_| anyhow::anyhow!("Error: could not open {}", path.as_ref().display()))?;
+        let mut tag = file
+            .tag()
+            .map_err(|_| anyhow::anyhow!("Error: no available tag found."))?;
+
+        tag.set_title(&self.title);
+        tag.set_artist(&self.artist);
+        tag.set_album(&self.album);
+        tag.set_comment(&self.comment);
+        tag.set_genre(&self.genre);
+        tag.set_year(self.year);
+        tag.set_track(self.track);
+
+        if !file.save() {
+            anyhow::bail!("Error: could not write metadata")
+        }
+
+        let codec = codec_of(path.as_ref());
+        if let (Some(sort_names), true) = (&self.sort_names, has_sort_names(&codec)) {
+            let value = |key: &str| {
+                sort_names
+                    .iter()
+                    .find(|(k, _)| k == key)
+                    .map_or(Patch::Clear, |(_, v)| Patch::Set(v.clone()))
+            };
+            let sorts = [ALBUM_SORT, TITLE_SORT, ARTIST_SORT].map(|key| (key, value(key)));
+            let sorts: Vec<_> = sorts.iter().map(|(k, p)| (*k, p)).collect();
+            write_sort_names(path.as_ref(), &sorts)?;
+        }
+        Ok(())
+    }
+}
+
+/// true if sort names of files of `codec` are read and written.
+/// MP4 atoms are not supported.
+fn has_sort_names(codec: &str) -> bool {
+    matches!(codec, "FLAC" | "MP3" | "Vorbis" | "Opus")
+}
+
+/// sort names of file at `path` by Vorbis comment key,
+/// None for unsupported formats or if the tags could not be read.
+fn read_sort_names(path: &Path) -> Option<Vec<(String, String)>> {
+    let comments = match codec_of(path).as_str() {
+        "FLAC" => FlacFile::open(path).and_then(|f| f.comments()).ok()?,
+        "Vorbis" | "Opus" => ogg::read_comments(path).ok()?,
+        "MP3" => {
+            let mut result = Vec::new();
+            for (key, id) in SORT_FRAMES {
+                if let Some(value) = id3::read_text(path, id).ok()? {
+                    result.push((key.to_string(), value));
+                }
+            }
+            return Some(result);
+        }
+        _ => return None,
+    };
+    Some(
+        [ALBUM_SORT, TITLE_SORT, ARTIST_SORT]
+            .iter()
+            .filter_map(|key| comments.get(key).map(|v| (key.to_string(), v.to_string())))
+            .collect(),
+    )
+}
+
+/// disc number of file at `path`, like `2` of `2/3`.
+/// read from FLAC, MP3 and Ogg files, None for other formats or if there is none.
+pub fn read_disc<P: AsRef<Path>>(path: P) -> Option<u32> {
+    let path = path.as_ref();
+    let value = match codec_of(path).as_str() {
+        "FLAC" => FlacFile::open(path)
+            .and_then(|f| f.comments())
+            .ok()?
+            .get(DISC_NUMBER)
+            .map(String::from),
+        "Vorbis" | "Opus" => ogg::read_comments(path)
+            .ok()?
+            .get(DISC_NUMBER)
+            .map(String::from),
+        "MP3" => id3::read_text(path, DISC_FRAME).ok()?,
+        _ => None,
+    }?;
+    value.split('/').next()?.trim().parse().ok()
+}
+
+/// guess codec from file extension, as taglib does not expose it
+fn codec_of(path: &Path) -> String {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    match ext.as_str() {
+        "flac" => "FLAC",
+        "mp3" => "MP3",
+        "m4a" | "mp4" => "MP4",
+        "aac" => "AAC",
+        "ogg" | "oga" => "Vorbis",
+        "opus" => "Opus",
+        "wav" => "PCM",
+        "aif" | "aiff" => "AIFF",
+        "ape" => "APE",
+        "wv" => "WavPack",
+        "wma" => "WMA",
+        _ => "",
+    }
+    .into()
 }
 
 impl TagLib {
@@ -15,9 +195,18 @@ impl TagLib {
         I: IntoIterator,
         I::Item: Borrow<Option<P>>,
     {
+        let mut codecs = Vec::new();
+        let mut file_paths = Vec::new();
         let result = paths
             .into_iter()
             .map(|b| {
+                file_paths.push(b.borrow().as_ref().map(|p| p.as_ref().to_path_buf()));
+                codecs.push(
+                    b.borrow()
+                        .as_ref()
+                        .map(|p| codec_of(p.as_ref()))
+                        .unwrap_or_default(),
+                );
                 b.borrow().as_ref().and_then(|p| {
                     File::new(p)
                         .map_err(|e| {
@@ -37,7 +226,149 @@ impl TagLib {
             })
             .collect();
 
-        Ok(TagLib { files: result })
+        Ok(TagLib {
+            files: result,
+            paths: file_paths,
+            codecs,
+        })
+    }
+}
+
+/// write sort names by Vorbis comment key to file at `path`:
+/// Vorbis comments of FLAC and Ogg files, TSOA/TSOT/TSOP frames of MP3 files.
+fn write_sort_names(path: &Path, sorts: &[(&str, &Patch<String>)]) -> anyhow::Result<()> {
+    let update = |comments: &mut VorbisComment| {
+        for (key, patch) in sorts {
+            match patch {
+                Patch::Keep => (),
+                Patch::Clear => comments.remove(key),
+                Patch::Set(v) => comments.set(key, v),
+            }
+        }
+    };
+
+    match codec_of(path).as_str() {
+        "FLAC" => {
+            let mut flac = FlacFile::open(path)?;
+            let mut comments = flac.comments()?;
+            update(&mut comments);
+            flac.set_comments(&comments);
+            flac.save()
+        }
+        "Vorbis" | "Opus" => {
+            let mut comments = ogg::read_comments(path)?;
+            update(&mut comments);
+            ogg::write_comments(path, &comments)
+        }
+        "MP3" => {
+            let frames: Vec<_> = sorts
+                .iter()
+                .filter_map(|(key, patch)| {
+                    let (_, id) = SORT_FRAMES.iter().find(|(k, _)| k == key)?;
+                    match patch {
+                        Patch::Keep => None,
+                        Patch::Clear => Some((*id, None)),
+                        Patch::Set(v) => Some((*id, Some(v.as_str()))),
+                    }
+                })
+                .collect();
+            id3::write_texts(path, &frames)
+        }
+        _ => Ok(()),
+    }
+}
+
+impl TagLib {
+    /// track numbers in tags, 0 if not set or the file could not be opened.
+    pub fn track_numbers(&self) -> Vec<u32> {
+        self.files
+            .iter()
+            .map(|f| {
+                f.as_ref()
+                    .and_then(|f| f.tag().ok())
+                    .and_then(|t| t.track())
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+
+    /// write only the fields changed by `patch`, leaving others as they are.
+    pub fn apply(&self, patch: &MetaPatch) -> anyhow::Result<()> {
+        let keep = TrackPatch::default();
+        let mut warned = false;
+
+        for (i, file) in self.files.iter().enumerate() {
+            if let Some(file) = file {
+                let track = patch.tracks.get(i).unwrap_or(&keep);
+                let mut tag = file
+                    .tag()
+                    .map_err(|_| anyhow::anyhow!("Error: no available tag found."))?;
+
+                if patch.track_numbers {
+                    tag.set_track((i + 1) as u32);
+                }
+                match track.album.or(&patch.album) {
+                    Patch::Keep => (),
+                    Patch::Clear => tag.set_album(""),
+                    Patch::Set(v) => tag.set_album(v),
+                }
+                match track.date.or(&patch.date) {
+                    Patch::Keep => (),
+                    Patch::Clear => tag.set_year(0),
+                    Patch::Set(v) => tag.set_year(*v),
+                }
+                match track.genre.or(&patch.genre) {
+                    Patch::Keep => (),
+                    Patch::Clear => tag.set_genre(""),
+                    Patch::Set(v) => tag.set_genre(v),
+                }
+                match &track.title {
+                    Patch::Keep => (),
+                    Patch::Clear => tag.set_title(""),
+                    Patch::Set(v) => tag.set_title(v),
+                }
+                match &track.artist {
+                    Patch::Keep => (),
+                    Patch::Clear => tag.set_artist(""),
+                    Patch::Set(v) => tag.set_artist(v),
+                }
+
+                if let (Some(expected), Ok(prop)) = (track.length, file.audioproperties()) {
+                    if prop.length().abs_diff(expected) > LENGTH_TOLERANCE {
+                        println!(
+                            "Warning: length of track {} differs: file {}, metadata {}",
+                            i + 1,
+                            format_length(prop.length()),
+                            format_length(expected)
+                        );
+                    }
+                }
+
+                let result = file.save();
+                if !result {
+                    anyhow::bail!("Error: could not write metadata")
+                }
+
+                let sorts = [
+                    (ALBUM_SORT, &patch.album_sort),
+                    (TITLE_SORT, &track.title_sort),
+                    (ARTIST_SORT, &track.artist_sort),
+                ];
+                if sorts.iter().all(|(_, p)| **p == Patch::Keep) {
+                    continue;
+                }
+                match (&self.paths[i], self.codecs[i].as_str()) {
+                    (Some(path), codec) if has_sort_names(codec) => write_sort_names(path, &sorts)?,
+                    _ if !warned => {
+                        println!("Warning: sort names are not written to MP4 and other formats");
+                        warned = true;
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        Ok(())
     }
 }
 
@@ -48,48 +379,58 @@ impl MetaFileIO for TagLib {
             .tag()
             .map_err(|_| anyhow::anyhow!("Error: no available tag found."))?;
         let mut tracks = Vec::with_capacity(self.files.len());
+        let mut album_sort = None;
 
-        for file in &self.files {
+        for (i, (file, codec)) in self.files.iter().zip(&self.codecs).enumerate() {
             if let Some(file) = file {
                 let tag = file
                     .tag()
                     .map_err(|_| anyhow::anyhow!("Error: no available tag found."))?;
-                tracks.push(Track::new(
+                let mut track = Track::new(
                     tag.title().unwrap_or_default(),
                     tag.artist().unwrap_or_default(),
-                ));
+                );
+
+                if let Ok(prop) = file.audioproperties() {
+                    track.length = Some(prop.length());
+                    track.audio = Some(AudioProperties {
+                        bitrate: prop.bitrate(),
+                        sample_rate: prop.samplerate(),
+                        channels: prop.channels(),
+                        codec: codec.clone(),
+                    });
+                }
+
+                track.disc = self.paths[i].as_ref().and_then(read_disc);
+
+                if let Some(sorts) = self.paths[i].as_deref().and_then(read_sort_names) {
+                    let get =
+                        |key: &str| sorts.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone());
+                    track.title_sort = get(TITLE_SORT);
+                    track.artist_sort = get(ARTIST_SORT);
+                    if album_sort.is_none() {
+                        album_sort = get(ALBUM_SORT);
+                    }
+                }
+
+                tracks.push(track);
             } else {
                 tracks.push(Track::new("", ""));
             }
         }
 
-        Ok(Metadata::new(
+        let mut result = Metadata::new(
             None,
             first_tag.album().unwrap_or_default(),
             first_tag.year().unwrap_or_default(),
             first_tag.genre().unwrap_or_default(),
             tracks,
-        ))
+        );
+        result.album_sort = album_sort;
+        Ok(result)
     }
 
     fn write(&self, meta: &Metadata) -> anyhow::Result<()> {
-        for (i, file) in self.files.iter().enumerate() {
-            if let Some(file) = file {
-                let mut tag = file.tag().unwrap();
-                tag.set_track((i + 1) as u32);
-                tag.set_album(&meta.album);
-                tag.set_year(meta.date);
-                tag.set_genre(&meta.genre);
-
-                tag.set_title(&meta.tracks[i].title);
-                tag.set_artist(&meta.tracks[i].artist);
-                let result = file.save();
-                if !result {
-                    anyhow::bail!("Error: could not write metadata")
-                }
-            }
-        }
-
-        Ok(())
+        self.apply(&MetaPatch::from(meta))
     }
 }
@@ -0,0 +1,150 @@
+use std::path::{Path, PathBuf};
+
+use toml_edit::{ArrayOfTables, Document, Item, Table, Value};
+
+use crate::{
+    info_struct::Metadata,
+    patch::MetaPatch,
+    schema::{self, Versioned},
+    traits::MetaFileIO,
+};
+
+/// metadata document in TOML, with the same schema and version as JSON and tracks as
+/// `[[tracks]]` tables.
+/// writing to an existing file keeps its comments and layout.
+/// TOML has no null, so fields can not be cleared by a patch.
+pub struct Toml {
+    path: PathBuf,
+}
+
+/// `meta` as a document, with tracks as array of tables instead of an inline array.
+fn to_document(meta: &Metadata) -> anyhow::Result<Document> {
+    let mut doc = toml_edit::ser::to_document(&Versioned::new(meta))?;
+
+    if let Some(Item::Value(Value::Array(tracks))) = doc.get("tracks") {
+        let mut tables = ArrayOfTables::new();
+        for track in tracks.iter() {
+            if let Value::InlineTable(t) = track {
+                tables.push(t.clone().into_table());
+            }
+        }
+        doc.insert("tracks", Item::ArrayOfTables(tables));
+    }
+
+    Ok(doc)
+}
+
+/// update `old` to the values of `new`, keeping comments and formatting of `old`.
+fn merge_table(old: &mut Table, new: &Table) {
+    let removed: Vec<_> = old
+        .iter()
+        .map(|(k, _)| k.to_string())
+        .filter(|k| !new.contains_key(k))
+        .collect();
+    for key in removed {
+        old.remove(&key);
+    }
+
+    for (key, item) in new.iter() {
+        match (old.get_mut(key), item) {
+            (Some(Item::Value(o)), Item::Value(n)) => {
+                let decor = o.decor().clone();
+                *o = n.clone();
+                *o.decor_mut() = decor;
+            }
+            (Some(Item::Table(o)), Item::Table(n)) => merge_table(o, n),
+            (Some(Item::ArrayOfTables(o)), Item::ArrayOfTables(n)) => {
+                let mut merged = ArrayOfTables::new();
+                for (i, n) in n.iter().enumerate() {
+                    match o.get(i) {
+                        Some(o) => {
+                            let mut o = o.clone();
+                            merge_table(&mut o, n);
+                            merged.push(o);
+                        }
+                        None => merged.push(n.clone()),
+                    }
+                }
+                *o = merged;
+            }
+            _ => {
+                old.insert(key, item.clone());
+            }
+        }
+    }
+}
+
+impl Toml {
+    pub fn new<P: AsRef<Path>>(path: P) -> Toml {
+        Toml {
+            path: path.as_ref().into(),
+        }
+    }
+
+    pub fn to_string(meta: &Metadata) -> anyhow::Result<String> {
+        Ok(to_document(meta)?.to_string())
+    }
+
+    /// read as a patch, where missing or blank fields are kept.
+    pub fn read_patch(&self) -> anyhow::Result<MetaPatch> {
+        let toml_str = std::fs::read_to_string(&self.path)?;
+        schema::from_value(toml_edit::de::from_str(&toml_str)?, &self.path)
+    }
+}
+
+impl MetaFileIO for Toml {
+    fn read(&self) -> anyhow::Result<Metadata> {
+        let toml_str = std::fs::read_to_string(&self.path)?;
+        schema::from_value(toml_edit::de::from_str(&toml_str)?, &self.path)
+    }
+
+    fn write(&self, meta: &Metadata) -> anyhow::Result<()> {
+        let new = to_document(meta)?;
+
+        let doc = if self.path.exists() {
+            let mut doc: Document = std::fs::read_to_string(&self.path)?.parse()?;
+            merge_table(doc.as_table_mut(), new.as_table());
+            doc
+        } else {
+            new
+        };
+
+        std::fs::write(&self.path, doc.to_string())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_comments_and_adds_version() {
+        let path = std::env::temp_dir().join(format!(
+            "music_info_{}_{}",
+            std::process::id(),
+            "versioned.toml"
+        ));
+        let toml = Toml::new(&path);
+        std::fs::write(
+            &path,
+            "# my album\nalbum = \"Album\"\ndate = 2000\ngenre = \"Rock\"\n\n[[tracks]]\ntitle = \"One\" # first\nartist = \"A\"\n",
+        )
+        .unwrap();
+
+        let mut meta = toml.read().unwrap();
+        meta.tracks[0].title = "Uno".into();
+        toml.write(&meta).unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let result = toml.read();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(text.contains("# my album"));
+        assert!(text.contains("title = \"Uno\" # first"));
+        assert!(text.contains(&format!("schema_version = {}", schema::VERSION)));
+        let result = result.unwrap();
+        assert_eq!(result.album, "Album");
+        assert_eq!(result.tracks[0].title, "Uno");
+    }
+}
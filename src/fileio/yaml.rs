@@ -0,0 +1,86 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    info_struct::Metadata,
+    patch::MetaPatch,
+    schema::{self, Versioned},
+    traits::MetaFileIO,
+};
+
+/// metadata document in YAML, with the same schema and version as JSON.
+/// comments are not kept on write.
+pub struct Yaml {
+    path: PathBuf,
+}
+
+impl Yaml {
+    pub fn new<P: AsRef<Path>>(path: P) -> Yaml {
+        Yaml {
+            path: path.as_ref().into(),
+        }
+    }
+
+    pub fn to_string(meta: &Metadata) -> anyhow::Result<String> {
+        Ok(serde_yaml::to_string(&Versioned::new(meta))?)
+    }
+
+    /// read as a patch, where missing or blank fields are kept and null (`~`) fields are cleared.
+    pub fn read_patch(&self) -> anyhow::Result<MetaPatch> {
+        let yaml_str = std::fs::read_to_string(&self.path)?;
+        schema::from_value(serde_yaml::from_str(&yaml_str)?, &self.path)
+    }
+}
+
+impl MetaFileIO for Yaml {
+    fn read(&self) -> anyhow::Result<Metadata> {
+        let yaml_str = std::fs::read_to_string(&self.path)?;
+        schema::from_value(serde_yaml::from_str(&yaml_str)?, &self.path)
+    }
+
+    fn write(&self, meta: &Metadata) -> anyhow::Result<()> {
+        std::fs::write(&self.path, Yaml::to_string(meta)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::info_struct::Track;
+
+    #[test]
+    fn versioned_round_trip() {
+        let path = std::env::temp_dir().join(format!(
+            "music_info_{}_{}",
+            std::process::id(),
+            "versioned.yaml"
+        ));
+        let yaml = Yaml::new(&path);
+
+        // unversioned documents are migrated
+        std::fs::write(
+            &path,
+            "album: Album\ndate: 2000\ngenre: Rock\ntracks:\n- title: One\n  artist: A\n",
+        )
+        .unwrap();
+        let mut meta = yaml.read().unwrap();
+        meta.tracks.push(Track::new("Two", "B"));
+        yaml.write(&meta).unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let result = yaml.read();
+        std::fs::write(&path, "schema_version: 99\nalbum: Album\n").unwrap();
+        let newer = yaml.read();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(text.starts_with(&format!("schema_version: {}\n", schema::VERSION)));
+        let titles: Vec<_> = result
+            .unwrap()
+            .tracks
+            .into_iter()
+            .map(|t| t.title)
+            .collect();
+        assert_eq!(titles, vec!["One", "Two"]);
+        assert!(newer.is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::{
+    info_struct::{Metadata, Track},
+    rename::{Segment, Template},
+};
+
+/// fields holding numbers, which only match digits.
+const NUMERIC: &[&str] = &["date", "date.year", "track", "tracks", "disc"];
+
+/// match `segments` against whole `text`, trying shorter field values first.
+fn match_segments(segments: &[Segment], text: &str, out: &mut HashMap<String, String>) -> bool {
+    let (first, rest) = match segments.split_first() {
+        Some(x) => x,
+        None => return text.is_empty(),
+    };
+
+    match first {
+        Segment::Literal(l) => match text.strip_prefix(l.as_str()) {
+            Some(text) => match_segments(rest, text, out),
+            None => false,
+        },
+        Segment::Field(name, _) => {
+            let numeric = NUMERIC.contains(&name.as_str());
+            let ends = text
+                .char_indices()
+                .skip(1)
+                .map(|(i, _)| i)
+                .chain(std::iter::once(text.len()));
+
+            for end in ends {
+                let value = &text[..end];
+                if value.ends_with('/')
+                    || (numeric && !value.ends_with(|c: char| c.is_ascii_digit()))
+                {
+                    // values never span directories, and numbers have only digits
+                    break;
+                }
+                if rest.is_empty() && end != text.len() {
+                    continue;
+                }
+                if match_segments(rest, &text[end..], out) {
+                    // on repeated fields, the first one in template wins
+                    out.insert(name.clone(), value.trim().to_string());
+                    return true;
+                }
+            }
+            false
+        }
+    }
+}
+
+/// field values parsed from `path` with `template` as a pattern,
+/// e.g. `{albumartist} - {album} ({date})/{track}. {title}` for `Artist - Album (2019)/03. Title.mp3`.
+///
+/// the pattern matches the last path components, without the extension of the file.
+/// None if `path` does not match.
+pub fn parse_path(template: &Template, path: &Path) -> Option<HashMap<String, String>> {
+    let depth = template
+        .segments()
+        .iter()
+        .map(|s| match s {
+            Segment::Literal(l) => l.matches('/').count(),
+            Segment::Field(..) => 0,
+        })
+        .sum::<usize>()
+        + 1;
+
+    let mut components: Vec<String> = Vec::with_capacity(depth);
+    components.push(path.file_stem()?.to_string_lossy().into_owned());
+    let mut dir = path.parent();
+    while components.len() < depth {
+        let d = dir?;
+        components.push(d.file_name()?.to_string_lossy().into_owned());
+        dir = d.parent();
+    }
+    components.reverse();
+
+    let mut result = HashMap::new();
+    if match_segments(template.segments(), &components.join("/"), &mut result) {
+        Some(result)
+    } else {
+        None
+    }
+}
+
+/// metadata draft of `files` from their paths.
+/// album-level fields are taken from the first file having them.
+pub fn draft<P: AsRef<Path>>(template: &Template, files: &[Option<P>]) -> Metadata {
+    let mut meta = Metadata::default();
+
+    for (i, file) in files.iter().enumerate() {
+        let mut track = Track::default();
+
+        if let Some(file) = file {
+            let file = file.as_ref();
+            // canonicalize so patterns can match directory names of relative paths
+            let path = std::fs::canonicalize(file).unwrap_or_else(|_| file.into());
+
+            match parse_path(template, &path) {
+                Some(fields) => {
+                    let get = |key: &str| fields.get(key).cloned().unwrap_or_default();
+                    let number = |key: &str| get(key).parse::<u32>().ok();
+
+                    if meta.album.is_empty() {
+                        meta.album = get("album");
+                    }
+                    if meta.genre.is_empty() {
+                        meta.genre = get("genre");
+                    }
+                    if meta.date == 0 {
+                        meta.date = number("date").or(number("date.year")).unwrap_or(0);
+                    }
+
+                    track.title = get("title");
+                    track.artist = match get("artist") {
+                        a if a.is_empty() => get("albumartist"),
+                        a => a,
+                    };
+                    track.disc = number("disc");
+
+                    match number("track") {
+                        Some(n) if n as usize != i + 1 => println!(
+                            "Warning: {} is track {} by name, but written as track {}",
+                            file.display(),
+                            n,
+                            i + 1
+                        ),
+                        _ => (),
+                    }
+                }
+                None => println!("Warning: {} does not match pattern", file.display()),
+            }
+        }
+
+        meta.tracks.push(track);
+    }
+
+    meta
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(template: &str, path: &str) -> Option<HashMap<String, String>> {
+        parse_path(&template.parse().unwrap(), Path::new(path))
+    }
+
+    #[test]
+    fn matches_directories_and_file_name() {
+        let fields = parse(
+            "{albumartist} - {album} ({date})/{track}. {title}",
+            "/music/Artist - Album (2019)/03. Title.mp3",
+        )
+        .unwrap();
+        assert_eq!(fields["albumartist"], "Artist");
+        assert_eq!(fields["album"], "Album");
+        assert_eq!(fields["date"], "2019");
+        assert_eq!(fields["track"], "03");
+        assert_eq!(fields["title"], "Title");
+    }
+
+    #[test]
+    fn numbers_match_only_digits() {
+        let fields = parse("{track} {title}", "03 5 Minutes.flac").unwrap();
+        assert_eq!(fields["track"], "03");
+        assert_eq!(fields["title"], "5 Minutes");
+
+        assert!(parse("{track} {title}", "Intro.flac").is_none());
+    }
+
+    #[test]
+    fn values_do_not_span_directories() {
+        assert!(parse("{album}/{title}", "/a/b/c.flac").is_some());
+        assert!(parse("{title}", "/a/b/c.flac").is_some());
+        assert!(parse("{album} - {title}", "/a - b/c.flac").is_none());
+    }
+
+    #[test]
+    fn first_of_repeated_fields_wins() {
+        let fields = parse("{artist}/{artist} - {title}", "/A/B - Song.flac").unwrap();
+        assert_eq!(fields["artist"], "A");
+    }
+}
@@ -0,0 +1,57 @@
+use std::{borrow::Borrow, path::Path, process::Command};
+
+use serde::Deserialize;
+
+/// Chromaprint fingerprint of an audio file, as used by AcoustID.
+/// computed by the external `fpcalc` program, which must be installed.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Fingerprint {
+    /// length in seconds
+    pub duration: f64,
+    /// compressed and base64 encoded fingerprint
+    pub fingerprint: String,
+}
+
+impl Fingerprint {
+    /// compute fingerprint with `fpcalc` from Chromaprint.
+    /// the executable can be changed with `FPCALC` environment variable.
+    pub fn compute<P: AsRef<Path>>(path: P) -> anyhow::Result<Fingerprint> {
+        let fpcalc = std::env::var_os("FPCALC").unwrap_or_else(|| "fpcalc".into());
+
+        let output = Command::new(&fpcalc)
+            .arg("-json")
+            .arg(path.as_ref())
+            .output()
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => anyhow::anyhow!(
+                    "Error: {} not found, install Chromaprint or set FPCALC to the path of fpcalc",
+                    fpcalc.to_string_lossy()
+                ),
+                _ => anyhow::anyhow!("Error: could not run {}: {}", fpcalc.to_string_lossy(), e),
+            })?;
+
+        if !output.status.success() {
+            anyhow::bail!(
+                "Error: fingerprint failed for {}: {}",
+                path.as_ref().display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+        }
+
+        let result = serde_json::from_slice(&output.stdout)?;
+        Ok(result)
+    }
+
+    /// compute fingerprints of files in the same form as `TagLib::new` takes.
+    pub fn compute_all<P, I>(paths: I) -> anyhow::Result<Vec<Option<Fingerprint>>>
+    where
+        P: AsRef<Path>,
+        I: IntoIterator,
+        I::Item: Borrow<Option<P>>,
+    {
+        paths
+            .into_iter()
+            .map(|b| b.borrow().as_ref().map(Fingerprint::compute).transpose())
+            .collect()
+    }
+}
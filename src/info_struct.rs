@@ -1,13 +1,35 @@
 use std::fmt;
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 pub type AddInfo = Vec<(String, String)>;
 
-#[derive(Serialize, Deserialize, Default, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Default, Debug, Clone)]
 pub struct Track {
     pub title: String,
     pub artist: String,
+
+    /// sort names, like `TITLESORT` and `ARTISTSORT` tags
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title_sort: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artist_sort: Option<String>,
+
+    /// disc number, 1-origin, for albums with several discs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disc: Option<u32>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub isrc: Option<String>,
+
+    /// length in seconds, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<u32>,
+
+    /// properties of audio file, only filled on read and never written to files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio: Option<AudioProperties>,
 }
 
 impl Track {
@@ -15,24 +37,62 @@ impl Track {
         Track {
             title: title.into(),
             artist: artist.into(),
+            title_sort: None,
+            artist_sort: None,
+            disc: None,
+            isrc: None,
+            length: None,
+            audio: None,
         }
     }
 }
 
+#[derive(Serialize, Deserialize, JsonSchema, Default, Debug, Clone, PartialEq, Eq)]
+pub struct AudioProperties {
+    /// bitrate in kb/s
+    pub bitrate: u32,
+    /// sample rate in Hz
+    pub sample_rate: u32,
+    pub channels: u32,
+    pub codec: String,
+}
+
+/// format seconds as `m:ss`
+pub fn format_length(length: u32) -> String {
+    format!("{}:{:02}", length / 60, length % 60)
+}
+
 impl fmt::Display for Track {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "title: {}\nartist: {}\n", self.title, self.artist)
     }
 }
 
-#[derive(Serialize, Deserialize, Default, Debug)]
+#[derive(Serialize, Deserialize, JsonSchema, Default, Debug, Clone)]
 pub struct Metadata {
-    #[serde(skip)]
+    /// provider ID the metadata was fetched from, kept so it can be fetched again
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub id: Option<String>,
 
     pub album: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album_sort: Option<String>,
     pub date: u32,
     pub genre: String,
+
+    /// freedb disc ID, as in `REM DISCID` of CUE sheets
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disc_id: Option<String>,
+
+    /// language and script of album and track titles, like `ja-Jpan` or `en-Latn`, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+
+    /// language and script of artist names, if known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artist_locale: Option<String>,
+
+    #[serde(default)]
     pub tracks: Vec<Track>,
 }
 
@@ -47,11 +107,34 @@ impl Metadata {
         Metadata {
             id: id.map(|x| x.into()),
             album: album.into(),
+            album_sort: None,
             date,
             genre: genre.into(),
+            disc_id: None,
+            locale: None,
+            artist_locale: None,
             tracks,
         }
     }
+
+    /// artist of the whole album: the artist shared by more than half of tracks,
+    /// or "Various Artists".
+    pub fn album_artist(&self) -> String {
+        let mut counts: Vec<(&str, usize)> = Vec::new();
+        for track in self.tracks.iter().filter(|t| !t.artist.is_empty()) {
+            match counts.iter_mut().find(|(a, _)| *a == track.artist) {
+                Some((_, n)) => *n += 1,
+                None => counts.push((&track.artist, 1)),
+            }
+        }
+
+        let total: usize = counts.iter().map(|(_, n)| n).sum();
+        match counts.iter().max_by_key(|(_, n)| *n) {
+            Some((artist, n)) if n * 2 > total => artist.to_string(),
+            Some(_) => "Various Artists".into(),
+            None => String::new(),
+        }
+    }
 }
 
 impl fmt::Display for Metadata {
@@ -77,6 +160,9 @@ impl fmt::Display for Metadata {
                 track.title,
                 track.artist
             )?;
+            if let Some(length) = track.length {
+                writeln!(f, "  track length: {}", format_length(length))?;
+            }
             if i < self.tracks.len() {
                 write!(f, "\n")?;
             }
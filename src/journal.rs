@@ -0,0 +1,422 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    fileio::{CueImage, FlacFile, Picture, RawTags, TagLib, TagLibPicture, VorbisComment},
+    patch::MetaPatch,
+    traits::PictureFileIO,
+};
+
+const ENTRY_FILE: &str = "entry.json";
+/// file name of saved CUE sheet in entry directory
+const CUE_FILE: &str = "cue";
+/// directory of saved covers in journal, shared by entries
+const COVER_DIR: &str = "covers";
+
+/// state of a file before a write.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileSnapshot {
+    pub path: PathBuf,
+    pub tags: RawTags,
+    /// file name of saved cover, None if file had no cover.
+    /// covers are saved once in the covers directory by SHA-1 of their data,
+    /// entries of older versions have them in entry directory
+    pub cover: Option<String>,
+    pub cover_mime: Option<String>,
+    /// true if file had a cover, even if it could not be saved
+    #[serde(default)]
+    pub had_cover: bool,
+    /// all Vorbis comments of FLAC files, including those taglib does not expose
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comments: Option<Vec<(String, String)>>,
+}
+
+/// one write operation recorded in journal.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Entry {
+    pub id: String,
+    /// unix time in seconds
+    pub time: u64,
+    pub command: String,
+    pub files: Vec<FileSnapshot>,
+    /// external CUE sheet of an image, saved in entry directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cue: Option<PathBuf>,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}  {}  {} ({} files)",
+            self.id,
+            format_time(self.time),
+            self.command,
+            self.files.len()
+        )
+    }
+}
+
+/// format unix time as `YYYY-MM-DD hh:mm:ss UTC`
+fn format_time(time: u64) -> String {
+    let days = (time / 86400) as i64;
+    let secs = time % 86400;
+
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        y,
+        m,
+        d,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// remove cover added to a file which had none. only FLAC files are supported.
+fn remove_cover(path: &Path) -> anyhow::Result<()> {
+    match FlacFile::open(path) {
+        Ok(mut flac) => {
+            flac.remove_pictures();
+            flac.save()
+        }
+        Err(_) => {
+            println!(
+                "Warning: {} had no cover, but covers can only be removed from FLAC files",
+                path.display()
+            );
+            Ok(())
+        }
+    }
+}
+
+/// snapshots of tags and covers taken before each write, to undo it later.
+pub struct Journal {
+    dir: PathBuf,
+}
+
+impl Journal {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Journal {
+        Journal {
+            dir: dir.as_ref().into(),
+        }
+    }
+
+    /// journal in the user data directory, e.g. `~/.local/share/music_info/journal`.
+    pub fn open_default() -> anyhow::Result<Journal> {
+        let data =
+            dirs::data_dir().ok_or(anyhow::anyhow!("Error: could not find user data directory"))?;
+        Ok(Journal::new(data.join("music_info").join("journal")))
+    }
+
+    /// save current tags and covers of `files` before `command` modifies them.
+    pub fn record<P: AsRef<Path>>(&self, command: &str, files: &[P]) -> anyhow::Result<Entry> {
+        self.record_with_cue(command, files, None)
+    }
+
+    /// same as `record`, also saving the text of CUE sheet `cue`.
+    fn record_with_cue<P: AsRef<Path>>(
+        &self,
+        command: &str,
+        files: &[P],
+        cue: Option<&Path>,
+    ) -> anyhow::Result<Entry> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("your system or rust std::time is broken");
+
+        // create_dir fails if the directory exists, so concurrent writers get distinct entries
+        std::fs::create_dir_all(&self.dir)?;
+        let mut id = format!("{}", now.as_millis());
+        let mut entry_dir = self.dir.join(&id);
+        let mut n = 1;
+        loop {
+            match std::fs::create_dir(&entry_dir) {
+                Ok(()) => break,
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    id = format!("{}-{}", now.as_millis(), n);
+                    entry_dir = self.dir.join(&id);
+                    n += 1;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let mut snapshots = Vec::with_capacity(files.len());
+        for file in files {
+            let path = std::fs::canonicalize(file.as_ref())?;
+            let tags = RawTags::read(&path)?;
+
+            let (cover, cover_mime) = match TagLibPicture::new(&path).and_then(|p| p.read()) {
+                Ok(pic) if !pic.raw.is_empty() => {
+                    (Some(self.save_cover(&pic.raw)?), Some(pic.mime))
+                }
+                _ => (None, None),
+            };
+
+            let (had_cover, comments) = match FlacFile::open(&path) {
+                Ok(flac) => (flac.has_pictures(), Some(flac.comments()?.comments)),
+                Err(_) => (cover.is_some(), None),
+            };
+
+            snapshots.push(FileSnapshot {
+                path,
+                tags,
+                cover,
+                cover_mime,
+                had_cover,
+                comments,
+            });
+        }
+
+        let cue = match cue {
+            Some(cue) => {
+                let path = std::fs::canonicalize(cue)?;
+                std::fs::copy(&path, entry_dir.join(CUE_FILE))?;
+                Some(path)
+            }
+            None => None,
+        };
+
+        let entry = Entry {
+            id,
+            time: now.as_secs(),
+            command: command.into(),
+            files: snapshots,
+            cue,
+        };
+        std::fs::write(
+            entry_dir.join(ENTRY_FILE),
+            serde_json::to_string_pretty(&entry)?,
+        )?;
+
+        Ok(entry)
+    }
+
+    /// save cover `raw` unless the same one is saved, returning its file name.
+    fn save_cover(&self, raw: &[u8]) -> anyhow::Result<String> {
+        let name = sha1_smol::Sha1::from(raw).digest().to_string();
+        let dir = self.dir.join(COVER_DIR);
+        std::fs::create_dir_all(&dir)?;
+
+        let path = dir.join(&name);
+        if !path.exists() {
+            // written under another name first, so a partial file is never taken as saved
+            let tmp = dir.join(format!("{}.tmp", name));
+            std::fs::write(&tmp, raw)?;
+            std::fs::rename(tmp, path)?;
+        }
+        Ok(name)
+    }
+
+    /// path of saved cover `name` of `entry`.
+    fn cover_path(&self, entry: &Entry, name: &str) -> PathBuf {
+        let shared = self.dir.join(COVER_DIR).join(name);
+        if shared.exists() {
+            shared
+        } else {
+            self.dir.join(&entry.id).join(name)
+        }
+    }
+
+    /// remove all but the latest `keep` entries, and covers no longer used by any entry.
+    /// returns the number of removed entries.
+    pub fn prune(&self, keep: usize) -> anyhow::Result<usize> {
+        let entries = self.list()?;
+        let removed = entries.len().saturating_sub(keep);
+        for entry in &entries[..removed] {
+            std::fs::remove_dir_all(self.dir.join(&entry.id))?;
+        }
+
+        let used: HashSet<_> = entries[removed..]
+            .iter()
+            .flat_map(|e| &e.files)
+            .filter_map(|f| f.cover.as_deref())
+            .collect();
+        let cover_dir = self.dir.join(COVER_DIR);
+        if cover_dir.exists() {
+            for file in std::fs::read_dir(cover_dir)? {
+                let file = file?;
+                if !used.contains(file.file_name().to_string_lossy().as_ref()) {
+                    std::fs::remove_file(file.path())?;
+                }
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// write `patch` and `picture` to `files`, after recording their current state.
+    /// all writes to tags of audio files should go through this to be undoable.
+    pub fn write<P: AsRef<Path>>(
+        &self,
+        command: &str,
+        files: &[Option<P>],
+        patch: &MetaPatch,
+        picture: Option<&Picture>,
+    ) -> anyhow::Result<Entry> {
+        let targets: Vec<_> = files.iter().flatten().collect();
+        let entry = self.record(command, &targets)?;
+
+        if let Some(pic) = picture {
+            for file in &targets {
+                TagLibPicture::new(file)?.write(pic)?;
+            }
+        }
+
+        TagLib::new(files)?.apply(patch)?;
+        Ok(entry)
+    }
+
+    /// same as `write` for a CUE `image`, also recording its external CUE sheet.
+    pub fn write_image(
+        &self,
+        command: &str,
+        image: &CueImage,
+        patch: &MetaPatch,
+        picture: Option<&Picture>,
+    ) -> anyhow::Result<Entry> {
+        let entry = self.record_with_cue(command, &[image.image()], image.cue())?;
+
+        image.apply(patch)?;
+        if let Some(pic) = picture {
+            TagLibPicture::new(image.image())?.write(pic)?;
+        }
+        Ok(entry)
+    }
+
+    /// all recorded entries, oldest first.
+    pub fn list(&self) -> anyhow::Result<Vec<Entry>> {
+        if !self.dir.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut result = Vec::new();
+        for dir in std::fs::read_dir(&self.dir)? {
+            let path = dir?.path().join(ENTRY_FILE);
+            if path.exists() {
+                let entry: Entry = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+                result.push(entry);
+            }
+        }
+
+        result.sort_by(|a, b| a.time.cmp(&b.time).then_with(|| a.id.cmp(&b.id)));
+        Ok(result)
+    }
+
+    pub fn get(&self, id: &str) -> anyhow::Result<Entry> {
+        let path = self.dir.join(id).join(ENTRY_FILE);
+        if !path.exists() {
+            anyhow::bail!("Error: no journal entry {}", id)
+        }
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// restore files to the state recorded in `entry`.
+    /// current state is recorded as a new entry first, so undo can be undone too.
+    pub fn restore(&self, entry: &Entry) -> anyhow::Result<Entry> {
+        let paths: Vec<_> = entry.files.iter().map(|f| &f.path).collect();
+        let backup =
+            self.record_with_cue(&format!("undo {}", entry.id), &paths, entry.cue.as_deref())?;
+
+        for (file, current) in entry.files.iter().zip(&backup.files) {
+            file.tags.write(&file.path)?;
+            if let Some(comments) = &file.comments {
+                let mut flac = FlacFile::open(&file.path)?;
+                let vendor = flac.comments()?.vendor;
+                flac.set_comments(&VorbisComment {
+                    vendor,
+                    comments: comments.clone(),
+                });
+                flac.save()?;
+            }
+
+            match (&file.cover, &file.cover_mime) {
+                (Some(name), Some(mime)) => {
+                    let raw = std::fs::read(self.cover_path(entry, name))?;
+                    TagLibPicture::new(&file.path)?.write(&Picture::new(raw, mime.clone()))?;
+                }
+                _ if file.had_cover => println!(
+                    "Warning: cover of {} was not saved, current cover kept",
+                    file.path.display()
+                ),
+                _ if current.had_cover => remove_cover(&file.path)?,
+                _ => (),
+            }
+        }
+
+        if let Some(cue) = &entry.cue {
+            std::fs::copy(self.dir.join(&entry.id).join(CUE_FILE), cue)?;
+        }
+
+        Ok(backup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// entry `id` with one file whose cover is `cover`, written to `journal`.
+    fn add_entry(journal: &Journal, id: &str, time: u64, cover: &[u8]) {
+        let entry = Entry {
+            id: id.into(),
+            time,
+            command: "write".into(),
+            files: vec![FileSnapshot {
+                path: "a.flac".into(),
+                tags: RawTags::default(),
+                cover: Some(journal.save_cover(cover).unwrap()),
+                cover_mime: Some("image/png".into()),
+                had_cover: true,
+                comments: None,
+            }],
+            cue: None,
+        };
+        let dir = journal.dir.join(id);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(ENTRY_FILE), serde_json::to_string(&entry).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn prune_keeps_latest_entries_and_their_covers() {
+        let dir = std::env::temp_dir().join(format!("music_info_{}_journal", std::process::id()));
+        let journal = Journal::new(&dir);
+        add_entry(&journal, "1", 1, b"old");
+        add_entry(&journal, "2", 2, b"shared");
+        add_entry(&journal, "3", 3, b"shared");
+        let covers = || std::fs::read_dir(dir.join(COVER_DIR)).unwrap().count();
+        // same cover is saved once
+        assert_eq!(covers(), 2);
+
+        let removed = journal.prune(2).unwrap();
+        let ids: Vec<_> = journal.list().unwrap().into_iter().map(|e| e.id).collect();
+        let remaining = covers();
+        let entry = journal.get("3").unwrap();
+        let cover =
+            std::fs::read(journal.cover_path(&entry, entry.files[0].cover.as_ref().unwrap()));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(removed, 1);
+        assert_eq!(ids, vec!["2", "3"]);
+        assert_eq!(remaining, 1);
+        assert_eq!(cover.unwrap(), b"shared");
+    }
+}
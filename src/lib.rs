@@ -1,4 +1,20 @@
+pub mod batch;
+pub mod clean;
+pub mod diff;
 pub mod fileio;
+pub mod filename;
+pub mod fingerprint;
 pub mod info_struct;
+pub mod journal;
+pub mod lint;
+pub mod matcher;
 pub mod net;
+pub mod normalize;
+pub mod patch;
+pub mod rename;
+pub mod scan;
+pub mod schema;
+pub mod search;
+pub mod sort_name;
+pub mod toc;
 pub mod traits;
@@ -0,0 +1,410 @@
+use std::{fmt, str::FromStr};
+
+use crate::info_struct::Metadata;
+
+/// earliest plausible release year, the year the phonograph was invented
+const EARLIEST_YEAR: u32 = 1877;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Off,
+    Warning,
+    Error,
+}
+
+impl FromStr for Severity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Severity> {
+        match s.trim().to_lowercase().as_str() {
+            "off" => Ok(Severity::Off),
+            "warning" | "warn" => Ok(Severity::Warning),
+            "error" => Ok(Severity::Error),
+            _ => anyhow::bail!("Error: unknown severity: {}", s),
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Severity::Off => "off",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    /// number of tracks differs from number of audio files
+    TrackCount,
+    EmptyTitle,
+    /// date not set, before recorded music or in the future
+    SuspiciousDate,
+    /// same title twice on a disc
+    DuplicateTitle,
+    /// leading, trailing or repeated whitespace
+    Whitespace,
+    /// full-width and half-width forms of the same characters in one value
+    MixedWidth,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 6] = [
+        Rule::TrackCount,
+        Rule::EmptyTitle,
+        Rule::SuspiciousDate,
+        Rule::DuplicateTitle,
+        Rule::Whitespace,
+        Rule::MixedWidth,
+    ];
+
+    pub fn default_severity(&self) -> Severity {
+        match self {
+            Rule::TrackCount | Rule::EmptyTitle => Severity::Error,
+            _ => Severity::Warning,
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Rule> {
+        Rule::ALL
+            .iter()
+            .find(|r| r.to_string() == s.trim())
+            .copied()
+            .ok_or(anyhow::anyhow!("Error: unknown lint rule: {}", s))
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Rule::TrackCount => "track-count",
+            Rule::EmptyTitle => "empty-title",
+            Rule::SuspiciousDate => "suspicious-date",
+            Rule::DuplicateTitle => "duplicate-title",
+            Rule::Whitespace => "whitespace",
+            Rule::MixedWidth => "mixed-width",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// severity of each rule, like `whitespace=error,mixed-width=off`.
+/// rules not given have their default severity.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rules {
+    overrides: Vec<(Rule, Severity)>,
+}
+
+impl FromStr for Rules {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Rules> {
+        let mut overrides = Vec::new();
+        for pair in s.split(',').filter(|p| !p.trim().is_empty()) {
+            let (rule, severity) = pair.split_once('=').ok_or(anyhow::anyhow!(
+                "Error: lint rule must be RULE=SEVERITY: {}",
+                pair
+            ))?;
+            overrides.push((Rule::from_str(rule)?, Severity::from_str(severity)?));
+        }
+        Ok(Rules { overrides })
+    }
+}
+
+impl Rules {
+    pub fn severity(&self, rule: Rule) -> Severity {
+        self.overrides
+            .iter()
+            .rev()
+            .find(|(r, _)| *r == rule)
+            .map_or_else(|| rule.default_severity(), |(_, s)| *s)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    Album,
+    /// 0-origin index of track
+    Track(usize),
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Location::Album => write!(f, "album"),
+            Location::Track(i) => write!(f, "track {}", i + 1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub rule: Rule,
+    pub severity: Severity,
+    pub location: Location,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}: {} [{}]",
+            self.severity, self.location, self.message, self.rule
+        )
+    }
+}
+
+/// collects issues of enabled rules.
+struct Report<'a> {
+    rules: &'a Rules,
+    issues: Vec<Issue>,
+}
+
+impl Report<'_> {
+    fn push<S: Into<String>>(&mut self, rule: Rule, location: Location, message: S) {
+        let severity = self.rules.severity(rule);
+        if severity != Severity::Off {
+            self.issues.push(Issue {
+                rule,
+                severity,
+                location,
+                message: message.into(),
+            });
+        }
+    }
+}
+
+fn current_year() -> u32 {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    1970 + (secs / 31_556_952) as u32
+}
+
+fn whitespace_problem(value: &str) -> Option<&'static str> {
+    if value.trim() != value {
+        Some("leading or trailing whitespace")
+    } else if value.contains("  ") {
+        Some("repeated spaces")
+    } else if value
+        .chars()
+        .any(|c| c.is_whitespace() && c != ' ' && c != '\u{3000}')
+    {
+        Some("tab or line break")
+    } else {
+        None
+    }
+}
+
+/// true if `value` has both full-width and half-width forms of letters and digits, or of katakana.
+fn is_mixed_width(value: &str) -> bool {
+    let has = |pred: fn(&char) -> bool| value.chars().any(|c| pred(&c));
+
+    // punctuation like `（` is common in Japanese titles, so only letters and digits count
+    let fullwidth_alnum = has(|c| {
+        ('\u{FF10}'..='\u{FF19}').contains(c)
+            || ('\u{FF21}'..='\u{FF3A}').contains(c)
+            || ('\u{FF41}'..='\u{FF5A}').contains(c)
+    });
+    let halfwidth_alnum = has(|c| c.is_ascii_alphanumeric());
+    let halfwidth_kana = has(|c| ('\u{FF65}'..='\u{FF9F}').contains(c));
+    let fullwidth_kana = has(|c| ('\u{30A1}'..='\u{30FA}').contains(c));
+
+    (fullwidth_alnum && halfwidth_alnum) || (halfwidth_kana && fullwidth_kana)
+}
+
+/// check text fields of `location` for whitespace and width problems.
+fn check_text(report: &mut Report, location: Location, field: &str, value: &str) {
+    if let Some(problem) = whitespace_problem(value) {
+        report.push(
+            Rule::Whitespace,
+            location,
+            format!("{} has {}: {:?}", field, problem, value),
+        );
+    }
+    if is_mixed_width(value) {
+        report.push(
+            Rule::MixedWidth,
+            location,
+            format!(
+                "{} mixes full-width and half-width characters: {}",
+                field, value
+            ),
+        );
+    }
+}
+
+/// issue if `tracks` tracks of metadata do not match `files` audio files.
+pub fn check_track_count(tracks: usize, files: usize, rules: &Rules) -> Option<Issue> {
+    let mut report = Report {
+        rules,
+        issues: Vec::new(),
+    };
+    if tracks != files {
+        report.push(
+            Rule::TrackCount,
+            Location::Album,
+            format!("{} tracks in metadata for {} audio files", tracks, files),
+        );
+    }
+    report.issues.pop()
+}
+
+/// check `meta` with `rules`. track count is checked if the number of audio files is given.
+pub fn check(meta: &Metadata, files: Option<usize>, rules: &Rules) -> Vec<Issue> {
+    let mut report = Report {
+        rules,
+        issues: Vec::new(),
+    };
+
+    if let Some(files) = files {
+        report
+            .issues
+            .extend(check_track_count(meta.tracks.len(), files, rules));
+    }
+
+    let year = current_year();
+    match meta.date {
+        0 => report.push(Rule::SuspiciousDate, Location::Album, "date is not set"),
+        d if d < EARLIEST_YEAR => report.push(
+            Rule::SuspiciousDate,
+            Location::Album,
+            format!("date {} is before recorded music", d),
+        ),
+        d if d > year + 1 => report.push(
+            Rule::SuspiciousDate,
+            Location::Album,
+            format!("date {} is in the future", d),
+        ),
+        _ => (),
+    }
+
+    check_text(&mut report, Location::Album, "album", &meta.album);
+    check_text(&mut report, Location::Album, "genre", &meta.genre);
+
+    for (i, track) in meta.tracks.iter().enumerate() {
+        let location = Location::Track(i);
+        if track.title.trim().is_empty() {
+            report.push(Rule::EmptyTitle, location, "title is empty");
+        }
+        check_text(&mut report, location, "title", &track.title);
+        check_text(&mut report, location, "artist", &track.artist);
+
+        let key = track.title.trim().to_lowercase();
+        let duplicate = meta.tracks[..i]
+            .iter()
+            .position(|t| t.disc == track.disc && t.title.trim().to_lowercase() == key);
+        if let (Some(first), false) = (duplicate, key.is_empty()) {
+            report.push(
+                Rule::DuplicateTitle,
+                location,
+                format!("same title as track {}: {}", first + 1, track.title),
+            );
+        }
+    }
+
+    report.issues
+}
+
+pub fn has_errors(issues: &[Issue]) -> bool {
+    issues.iter().any(|i| i.severity == Severity::Error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::info_struct::Track;
+
+    fn meta(date: u32, titles: &[(Option<u32>, &str)]) -> Metadata {
+        let tracks = titles
+            .iter()
+            .map(|(disc, title)| {
+                let mut track = Track::new(*title, "Artist");
+                track.disc = *disc;
+                track
+            })
+            .collect();
+        Metadata::new(None, "Album", date, "Rock", tracks)
+    }
+
+    fn rules_of(issues: &[Issue]) -> Vec<Rule> {
+        issues.iter().map(|i| i.rule).collect()
+    }
+
+    #[test]
+    fn mixed_width() {
+        assert!(is_mixed_width("ＡＢＣ123"));
+        assert!(is_mixed_width("ｶタカナ"));
+        assert!(!is_mixed_width("ＡＢＣ１２３"));
+        assert!(!is_mixed_width("曲（Live）2020"));
+        assert!(!is_mixed_width("カタカナ ABC"));
+    }
+
+    #[test]
+    fn whitespace() {
+        assert_eq!(
+            whitespace_problem(" Title"),
+            Some("leading or trailing whitespace")
+        );
+        assert_eq!(whitespace_problem("A  B"), Some("repeated spaces"));
+        assert_eq!(whitespace_problem("A\tB"), Some("tab or line break"));
+        assert_eq!(whitespace_problem("全角\u{3000}空白"), None);
+        assert_eq!(whitespace_problem("A B"), None);
+    }
+
+    #[test]
+    fn duplicate_titles_per_disc() {
+        let meta = meta(
+            2000,
+            &[(Some(1), "Intro"), (Some(1), "intro "), (Some(2), "Intro")],
+        );
+        let issues = check(&meta, None, &Rules::default());
+        let duplicates: Vec<_> = issues
+            .iter()
+            .filter(|i| i.rule == Rule::DuplicateTitle)
+            .map(|i| i.location)
+            .collect();
+        assert_eq!(duplicates, vec![Location::Track(1)]);
+    }
+
+    #[test]
+    fn overrides_severity() {
+        let rules = Rules::from_str("whitespace=error, mixed-width=off").unwrap();
+        assert_eq!(rules.severity(Rule::Whitespace), Severity::Error);
+        assert_eq!(rules.severity(Rule::MixedWidth), Severity::Off);
+        assert_eq!(rules.severity(Rule::EmptyTitle), Severity::Error);
+        assert_eq!(rules.severity(Rule::DuplicateTitle), Severity::Warning);
+
+        let issues = check(&meta(2000, &[(None, "Ａ1 ")]), Some(1), &rules);
+        assert_eq!(rules_of(&issues), vec![Rule::Whitespace]);
+        assert!(has_errors(&issues));
+
+        assert!(Rules::from_str("whitespace").is_err());
+        assert!(Rules::from_str("unknown=off").is_err());
+        assert!(Rules::from_str("whitespace=loud").is_err());
+    }
+
+    #[test]
+    fn date_bounds() {
+        let year = current_year();
+        let suspicious = |date| {
+            rules_of(&check(&meta(date, &[(None, "T")]), None, &Rules::default()))
+                .contains(&Rule::SuspiciousDate)
+        };
+        assert!(suspicious(0));
+        assert!(suspicious(EARLIEST_YEAR - 1));
+        assert!(!suspicious(EARLIEST_YEAR));
+        assert!(!suspicious(year + 1));
+        assert!(suspicious(year + 2));
+        assert!(year >= 2024);
+    }
+}
@@ -1,50 +1,256 @@
-use std::{path::PathBuf, str::FromStr};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Mutex,
+};
 
 use clap::{Parser, Subcommand};
+use crossterm::tty::IsTty;
+
+mod picker;
 
 use music_info::{
-    fileio::{Json, Picture, TagLib, TagLibPicture},
-    info_struct::{Metadata, Track},
-    net::{MusicBrainz, Spotify},
+    batch::{self, Action, Outcome},
+    clean::Cleaner,
+    diff::MetaDiff,
+    fileio::{self, ColumnMap, CueImage, DocumentOptions, Json, Picture, TagLib, TagLibPicture},
+    filename,
+    fingerprint::Fingerprint,
+    info_struct::{AddInfo, Metadata, Track},
+    journal::{Entry, Journal},
+    lint::{self, Rules, Severity},
+    matcher,
+    net::{AcoustId, Locale, MusicBrainz, Provider, ResourceId, Spotify},
+    normalize::Normalization,
+    patch::{Field, MetaPatch},
+    rename::{self, Filesystem, Template},
+    scan::{scan_dir, Album, Order},
+    schema,
+    search::SearchTerms,
+    toc::Toc,
     traits::*,
 };
 
+/// number of candidates shown by autotag
+const AUTOTAG_CANDIDATES: usize = 10;
+
 #[derive(Parser, Debug)]
 #[clap(author, about, version)]
 struct Cmd {
     #[clap(subcommand)]
     op: Opr,
+
+    /// column mapping of csv and tsv tracklists, like "曲名=title,歌手=artist".
+    /// fields are album, albumartist, date, genre, disc, track, title, artist, isrc and length
+    #[clap(long, global = true)]
+    columns: Option<ColumnMap>,
+
+    /// severity of lint rules used by lint and write, like "whitespace=error,mixed-width=off".
+    /// rules are track-count, empty-title, suspicious-date, duplicate-title, whitespace
+    /// and mixed-width, severities are off, warning and error
+    #[clap(long, global = true)]
+    rules: Option<Rules>,
+
+    /// apply cleanup rules to fetched metadata and to metadata files before write
+    #[clap(long, global = true)]
+    clean: bool,
+
+    /// JSON file of cleanup rules, ~/.music_info_clean.json if it exists,
+    /// built-in rules otherwise
+    #[clap(long, global = true, value_name = "FILE")]
+    clean_rules: Option<PathBuf>,
+
+    /// normalize text of fetched metadata and metadata files before write, before cleanup rules.
+    /// comma separated: nfc or nfkc, alnum (full-width letters and digits to half-width),
+    /// kana (half-width katakana to full-width), punct (wave dashes, quotes and hyphens).
+    /// per-field normalization is set with the normalize action of cleanup rules
+    #[clap(long, global = true, value_name = "OPTIONS")]
+    normalize: Option<Normalization>,
+
+    /// preferred language and script of names fetched from musicbrainz, like en, ja or ja-Latn.
+    /// titles come from pseudo-releases and artists from aliases, falling back to the originals
+    #[clap(long, global = true)]
+    locale: Option<Locale>,
 }
 
 #[derive(Subcommand, Debug)]
 enum Opr {
     Read {
-        /// output json file
+        /// output metadata file, json, yaml, toml, csv or cue by extension,
+        /// relative to each album directory if several albums are read.
+        /// rewriting a yaml file drops its comments, toml keeps them
         #[clap(short, long)]
         json: Option<PathBuf>,
 
-        /// output picture file
+        /// output picture file, relative to each album directory if several albums are read
         #[clap(short, long)]
         picture: Option<PathBuf>,
 
-        /// source audio file
+        /// take metadata from file and directory names instead of tags, with a pattern like
+        /// `{albumartist} - {album} ({date})/{track}. {title}`, fields as in rename
+        #[clap(long, value_name = "PATTERN")]
+        from_filename: Option<Template>,
+
+        /// treat each audio file as a single-file image of a whole disc split by a CUE sheet.
+        #[clap(long)]
+        image: bool,
+
+        /// CUE sheet of the image, `<image>.cue` or the embedded cuesheet if omitted
+        #[clap(long, requires = "image")]
+        cue: Option<PathBuf>,
+
+        /// search directories recursively, each directory with audio files is an album,
+        /// also if it has subdirectories
+        #[clap(short, long)]
+        recursive: bool,
+
+        /// order of files found in directories: natural (file name)
+        /// or tags (disc and track number)
+        #[clap(long, default_value = "natural")]
+        order: Order,
+
+        /// source audio files or directories
         #[clap(required = true)]
         audio: Vec<String>,
     },
     Write {
-        /// input json file
-        #[clap(short, long)]
-        json: PathBuf,
+        /// input metadata file, json, yaml, toml, csv or cue by extension.
+        /// in json, missing or blank fields are left as is and null fields are cleared.
+        /// sort names are written to FLAC, MP3 and Ogg files, MP4 and other formats keep theirs
+        #[clap(short, long, required_unless_present = "picture")]
+        json: Option<PathBuf>,
 
         /// input picture file
         #[clap(short, long)]
         picture: Option<PathBuf>,
 
-        /// target audio files
+        /// comma separated fields to write, all fields if omitted.
+        /// available: album, date, genre, title, artist, track, cover
+        #[clap(short, long, value_delimiter = ',')]
+        fields: Vec<Field>,
+
+        /// treat each audio file as a single-file image of a whole disc split by a CUE sheet
+        #[clap(long)]
+        image: bool,
+
+        /// CUE sheet of the image, `<image>.cue` or the embedded cuesheet if omitted
+        #[clap(long, requires = "image")]
+        cue: Option<PathBuf>,
+
+        /// show changes without writing
+        #[clap(short = 'n', long)]
+        dry_run: bool,
+
+        /// write even if lint finds errors in the new metadata
+        #[clap(long)]
+        force: bool,
+
+        /// search directories recursively, each directory with audio files is an album,
+        /// also if it has subdirectories
+        #[clap(short, long)]
+        recursive: bool,
+
+        /// order of files found in directories: natural (file name)
+        /// or tags (disc and track number)
+        #[clap(long, default_value = "natural")]
+        order: Order,
+
+        /// target audio files or directories.
+        /// if several albums are given, json and picture are relative to each album directory
+        #[clap(required = true)]
+        audio: Vec<String>,
+    },
+    /// rewrite metadata with cleanup rules, like moving featured artists from titles to artists
+    Clean {
+        /// metadata file to clean instead of tags, relative to each album directory
+        /// if several albums are given
+        #[clap(short, long)]
+        json: Option<PathBuf>,
+
+        /// show changes without writing
+        #[clap(short = 'n', long)]
+        dry_run: bool,
+
+        /// search directories recursively, each directory with audio files is an album,
+        /// also if it has subdirectories
+        #[clap(short, long)]
+        recursive: bool,
+
+        /// order of files found in directories: natural (file name)
+        /// or tags (disc and track number)
+        #[clap(long, default_value = "natural")]
+        order: Order,
+
+        /// provider the metadata came from, for rules limited to providers.
+        /// tags and documents without ID are local otherwise,
+        /// e.g. use `spotify` to remove remaster suffixes from files tagged from Spotify
+        #[clap(long)]
+        provider: Option<Provider>,
+
+        /// audio files or directories
         #[clap(required = true)]
         audio: Vec<String>,
     },
+    /// check metadata for problems like empty titles, suspicious dates and stray whitespace
+    Lint {
+        /// metadata file to check instead of tags, relative to each album directory
+        /// if several albums are given
+        #[clap(short, long)]
+        json: Option<PathBuf>,
+
+        /// search directories recursively, each directory with audio files is an album,
+        /// also if it has subdirectories
+        #[clap(short, long)]
+        recursive: bool,
+
+        /// order of files found in directories: natural (file name)
+        /// or tags (disc and track number)
+        #[clap(long, default_value = "natural")]
+        order: Order,
+
+        /// audio files or directories
+        #[clap(required = true)]
+        audio: Vec<String>,
+    },
+    /// show field-by-field changes from current metadata to JSON
+    Diff {
+        /// picture file to compare with covers of audio files
+        #[clap(short, long)]
+        picture: Option<PathBuf>,
+
+        /// show unchanged fields and tracks too
+        #[clap(short, long)]
+        verbose: bool,
+
+        /// metadata file (json, yaml, toml, csv or cue) with new metadata
+        json: PathBuf,
+
+        /// metadata file with current metadata, or audio files
+        #[clap(required = true)]
+        current: Vec<String>,
+    },
     Query {
+        /// audio files to compare with results, results are ranked by similarity
+        #[clap(short, long = "match", global = true)]
+        match_audio: Vec<String>,
+
+        /// pick a result interactively, then save it like fetch
+        #[clap(long, global = true)]
+        pick: bool,
+
+        /// with --pick, output file to save metadata, json, yaml, toml, csv or cue by extension
+        #[clap(short, long, global = true, requires = "pick")]
+        output: Option<PathBuf>,
+
+        /// with --pick, save picture to PICTURE. with --write, it is also written to audio files
+        #[clap(short, long, global = true, requires = "pick")]
+        picture: Option<PathBuf>,
+
+        /// with --pick, write picked metadata to the files given by --match
+        #[clap(short, long, global = true, requires_all = &["pick", "match_audio"], conflicts_with = "output")]
+        write: bool,
+
         #[clap(subcommand)]
         opr: QueryOpr,
     },
@@ -52,10 +258,160 @@ enum Opr {
         #[clap(subcommand)]
         opr: FetchOpr,
     },
+    /// find releases by barcode (EAN/UPC) or catalog number on all available providers
+    Lookup {
+        /// barcode or catalog number
+        code: String,
+    },
     Template {
         #[clap(subcommand)]
         opr: TempOpr,
     },
+    /// print JSON Schema of metadata documents, for validation in editors
+    Schema {
+        /// output file to write schema
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// list write operations recorded for undo
+    History {
+        /// number of entries to show
+        #[clap(short = 'n', long, default_value_t = 20)]
+        count: usize,
+
+        /// remove all but the latest KEEP entries, and covers saved only for removed ones
+        #[clap(long, value_name = "KEEP")]
+        prune: Option<usize>,
+    },
+    /// restore tags and covers to the state before a write operation
+    Undo {
+        /// ID of journal entry, the latest one if omitted
+        id: Option<String>,
+    },
+    /// search, pick and write metadata and cover to audio files in one pass.
+    /// sort names are written to FLAC, MP3 and Ogg files, not to MP4
+    Autotag {
+        /// providers to search, comma separated
+        #[clap(
+            short,
+            long,
+            value_delimiter = ',',
+            default_value = "musicbrainz,spotify"
+        )]
+        providers: Vec<Provider>,
+
+        /// album and artist to search, derived from existing tags or directory name if omitted
+        #[clap(short, long)]
+        album: Option<String>,
+
+        /// artist to search together with --album
+        #[clap(long, requires = "album")]
+        artist: Option<String>,
+
+        /// derive album and artist to search from file and directory names with a pattern,
+        /// fields as in rename
+        #[clap(long, value_name = "PATTERN", conflicts_with = "album")]
+        from_filename: Option<Template>,
+
+        /// do not ask, take the best match if its distance is below THRESHOLD
+        #[clap(short, long)]
+        yes: bool,
+
+        /// maximum match distance accepted with --yes
+        #[clap(short, long, default_value_t = 0.2)]
+        threshold: f64,
+
+        /// do not fetch and write cover
+        #[clap(long)]
+        no_picture: bool,
+
+        /// target audio files
+        #[clap(required = true)]
+        audio: Vec<String>,
+    },
+    /// move and rename files to paths rendered from tags
+    Rename {
+        /// path template, fields: album, albumartist, artist, title, genre, date, date.year,
+        /// track, tracks, disc. numbers can be padded like {track:02}.
+        /// disc is read from FLAC, MP3 and Ogg files, 1 if unknown
+        #[clap(
+            short,
+            long,
+            default_value = "{albumartist}/{date.year} - {album}/{track:02} {title}"
+        )]
+        template: Template,
+
+        /// directory the rendered paths are relative to
+        #[clap(short, long, default_value = ".")]
+        dest: PathBuf,
+
+        /// take metadata from json, yaml, toml, csv or cue file instead of tags,
+        /// relative to each album directory if several albums are given
+        #[clap(short, long)]
+        json: Option<PathBuf>,
+
+        /// characters allowed in file names: windows (portable) or posix
+        #[clap(long, default_value = "windows")]
+        fs: Filesystem,
+
+        /// show moves without doing them
+        #[clap(short = 'n', long)]
+        dry_run: bool,
+
+        /// search directories recursively, each directory with audio files is an album,
+        /// also if it has subdirectories
+        #[clap(short, long)]
+        recursive: bool,
+
+        /// order of files found in directories: natural (file name)
+        /// or tags (disc and track number)
+        #[clap(long, default_value = "natural")]
+        order: Order,
+
+        /// target audio files or directories
+        #[clap(required = true)]
+        audio: Vec<String>,
+    },
+    /// run an action on every album under a library root.
+    /// each directory containing audio files is an album
+    Batch {
+        /// export: save tags to metadata file,
+        /// refresh: fetch again by the provider ID in the `id` field of metadata file
+        /// and write to files, albums without it are skipped,
+        /// cover: embed cover file
+        action: Action,
+
+        /// library root directory
+        root: PathBuf,
+
+        /// metadata file name in each album directory
+        #[clap(short, long, default_value = "metadata.json")]
+        json: PathBuf,
+
+        /// cover file name in each album directory
+        #[clap(short, long, default_value = "cover.jpg")]
+        picture: PathBuf,
+
+        /// on refresh, also fetch cover, save it as cover file and embed it
+        #[clap(long)]
+        fetch_picture: bool,
+
+        /// on export, overwrite existing metadata files
+        #[clap(short, long)]
+        force: bool,
+
+        /// order of files in album: natural (file name) or tags (disc and track number)
+        #[clap(long, default_value = "natural")]
+        order: Order,
+
+        /// number of worker threads, number of CPUs if omitted
+        #[clap(long)]
+        jobs: Option<usize>,
+
+        /// file to record processed albums, an interrupted run resumes from it
+        #[clap(short, long)]
+        state: Option<PathBuf>,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -68,12 +424,50 @@ enum QueryOpr {
         /// query parameter to find info
         query: String,
     },
+    /// find musicbrainz releases by disc ID computed from CUE sheet, rip log or track offsets
+    Discid {
+        /// CUE sheet of the rip
+        #[clap(long, conflicts_with_all = &["log", "offsets"])]
+        cue: Option<PathBuf>,
+
+        /// EAC or XLD log of the rip
+        #[clap(long, conflicts_with = "offsets")]
+        log: Option<PathBuf>,
+
+        /// comma separated start sectors of each track, including 150 sectors pregap
+        #[clap(long, value_delimiter = ',', requires = "leadout")]
+        offsets: Vec<u32>,
+
+        /// leadout sector, computed from audio files referenced by CUE sheet if omitted.
+        /// logs have their own leadout
+        #[clap(long, conflicts_with = "log")]
+        leadout: Option<u32>,
+    },
+    /// find musicbrainz releases by acoustic fingerprint of audio files.
+    /// requires fpcalc of Chromaprint in PATH, or its path in the FPCALC environment variable
+    Acoustid {
+        /// acoustid API key, read from ~/.acoustid_key if omitted
+        #[clap(short, long)]
+        key: Option<String>,
+
+        /// base url of acoustid server
+        #[clap(long)]
+        base_url: Option<String>,
+
+        /// maximum number of releases to fetch
+        #[clap(short, long, default_value_t = 5)]
+        limit: usize,
+
+        /// audio files to identify
+        #[clap(required = true)]
+        audio: Vec<String>,
+    },
 }
 
 #[derive(Subcommand, Debug)]
 enum FetchOpr {
     MusicBrainz {
-        /// output file to save json
+        /// output file to save metadata, json, yaml, toml, csv or cue by extension
         #[clap(short, long)]
         output: Option<PathBuf>,
 
@@ -81,11 +475,11 @@ enum FetchOpr {
         #[clap(short, long)]
         picture: Option<PathBuf>,
 
-        /// MBID of target release
+        /// MBID or musicbrainz url of target release or release group
         id: String,
     },
     Spotify {
-        /// output file to save json
+        /// output file to save metadata, json, yaml, toml, csv or cue by extension
         #[clap(short, long)]
         output: Option<PathBuf>,
 
@@ -93,15 +487,29 @@ enum FetchOpr {
         #[clap(short, long)]
         picture: Option<PathBuf>,
 
-        /// spotify ID of target album
+        /// spotify ID, url or uri of target album or track
         id: String,
     },
+    Auto {
+        /// output file to save metadata, json, yaml, toml, csv or cue by extension
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+
+        /// if present, save picture to PICTURE
+        #[clap(short, long)]
+        picture: Option<PathBuf>,
+
+        /// url, uri or ID of target release, provider is detected automatically
+        url: String,
+    },
 }
 
 #[derive(Subcommand, Debug)]
 enum TempOpr {
     Json {
-        /// output file to write template
+        /// output file to write template, format chosen by extension as other documents.
+        /// comments added to yaml are dropped when it is written again, toml keeps them.
+        /// JSON is printed if omitted
         #[clap(short, long)]
         output: Option<PathBuf>,
     },
@@ -128,114 +536,949 @@ fn audio_files_parser(files: Vec<String>) -> anyhow::Result<Vec<Option<String>>>
     Ok(result)
 }
 
+/// audio files of one album given on command line.
+struct AlbumInput {
+    /// directory the album was found in, None for files given directly
+    dir: Option<PathBuf>,
+    files: Vec<Option<String>>,
+}
+
+impl AlbumInput {
+    /// resolve relative `path` against album directory if there are `multiple` albums.
+    fn resolve(&self, path: &Path, multiple: bool) -> PathBuf {
+        match &self.dir {
+            Some(dir) if multiple && path.is_relative() => dir.join(path),
+            _ => path.into(),
+        }
+    }
+}
+
+/// files given directly form one album, and each directory with audio files is an album.
+fn album_inputs(
+    audio: Vec<String>,
+    recursive: bool,
+    order: Order,
+) -> anyhow::Result<Vec<AlbumInput>> {
+    let (dirs, files): (Vec<_>, Vec<_>) = audio.into_iter().partition(|a| Path::new(a).is_dir());
+    let mut result = Vec::new();
+
+    if !files.is_empty() {
+        result.push(AlbumInput {
+            dir: None,
+            files: audio_files_parser(files)?,
+        });
+    }
+    for dir in dirs {
+        for album in scan_dir(dir, recursive, order)? {
+            result.push(AlbumInput {
+                dir: Some(album.dir),
+                files: album
+                    .files
+                    .into_iter()
+                    .map(|f| Some(f.to_string_lossy().into_owned()))
+                    .collect(),
+            });
+        }
+    }
+
+    if result.is_empty() {
+        anyhow::bail!("Error: no audio file found")
+    }
+    Ok(result)
+}
+
+/// image backend of the single audio file of `album`.
+fn cue_image(album: &AlbumInput, cue: Option<&PathBuf>) -> anyhow::Result<CueImage> {
+    let files: Vec<_> = album.files.iter().flatten().collect();
+    let image = match files.as_slice() {
+        [image] => PathBuf::from(image),
+        _ => anyhow::bail!("Error: an image must be a single audio file"),
+    };
+
+    Ok(match cue {
+        Some(cue) => CueImage::new(image, Some(cue.clone())),
+        None => CueImage::find(image),
+    })
+}
+
+fn spotify_client() -> anyhow::Result<Spotify> {
+    let default_cred_path = dirs::home_dir().unwrap().join(".spotify_cred.json");
+    let default_token_path = dirs::home_dir().unwrap().join(".spotify_token.json");
+
+    Spotify::new(default_cred_path, default_token_path)
+}
+
+fn fetch_with<C: FetchMeta + FetchPicture>(
+    client: &C,
+    id: &str,
+    picture: Option<PathBuf>,
+) -> anyhow::Result<(Metadata, Option<(Picture, PathBuf)>)> {
+    let result = client.fetch(id)?;
+
+    let picture = picture
+        .map(|path| client.fetch_picture(id).map(|data| (data, path)))
+        .transpose()?;
+
+    Ok((result, picture))
+}
+
+fn fetch_resource(
+    res: &ResourceId,
+    picture: Option<PathBuf>,
+    locale: Option<&Locale>,
+) -> anyhow::Result<(Metadata, Option<(Picture, PathBuf)>)> {
+    match res.provider {
+        Provider::MusicBrainz => {
+            let client = MusicBrainz::new().with_locale(locale.cloned());
+            let id = client.release_id(res)?;
+            fetch_with(&client, &id, picture)
+        }
+        Provider::Spotify => {
+            let client = spotify_client()?;
+            let id = client.album_id(res)?;
+            fetch_with(&client, &id, picture)
+        }
+    }
+}
+
+/// sort candidates by similarity to `local`, adding match details to additional info.
+fn rank_candidates(local: &Metadata, result: Vec<(Metadata, AddInfo)>) -> Vec<(Metadata, AddInfo)> {
+    let ranking = matcher::rank(local, result.iter().map(|x| &x.0));
+    let mut result: Vec<_> = result.into_iter().map(Some).collect();
+
+    ranking
+        .into_iter()
+        .map(|(idx, m)| {
+            let (meta, mut add_info) = result[idx].take().unwrap();
+            add_info.insert(0, ("match distance".into(), m.to_string()));
+            (meta, add_info)
+        })
+        .collect()
+}
+
+fn fetch_picture_of(provider: Provider, id: &str) -> anyhow::Result<Picture> {
+    match provider {
+        Provider::MusicBrainz => MusicBrainz::new().fetch_picture(id),
+        Provider::Spotify => spotify_client()?.fetch_picture(id),
+    }
+}
+
+fn print_candidates(header: &str, result: &[(Metadata, AddInfo)]) {
+    if result.is_empty() {
+        println!("no result found.");
+        return;
+    }
+
+    let last_idx = result.len() - 1;
+    println!("{}", header);
+    for (idx, (meta, add_info)) in result.iter().enumerate() {
+        print!("{}", meta);
+
+        if !add_info.is_empty() {
+            println!("additional info:")
+        }
+        for (k, v) in add_info {
+            println!("  {}: {}", k, v);
+        }
+
+        if idx != last_idx {
+            print!("\n\n")
+        }
+    }
+}
+
+/// write `meta` and `picture` to `files`, after recording their current state to journal.
+fn write_files(
+    command: &str,
+    files: Vec<Option<String>>,
+    patch: &MetaPatch,
+    picture: Option<&Picture>,
+) -> anyhow::Result<()> {
+    let entry = journaled_write(command, files, patch, picture)?;
+    println!(
+        "backup saved as {}, run `undo {}` to restore",
+        entry.id, entry.id
+    );
+    Ok(())
+}
+
+/// same as `write_files`, but quiet and returns journal entry.
+fn journaled_write(
+    command: &str,
+    files: Vec<Option<String>>,
+    patch: &MetaPatch,
+    picture: Option<&Picture>,
+) -> anyhow::Result<Entry> {
+    Journal::open_default()?.write(command, &files, patch, picture)
+}
+
+/// print lint issues of metadata after applying `patch` to `current`,
+/// failing on errors unless `force`. track count is checked against `files` if given.
+fn lint_write(
+    current: &Metadata,
+    patch: &MetaPatch,
+    files: Option<usize>,
+    rules: &Rules,
+    force: bool,
+) -> anyhow::Result<()> {
+    let mut issues = lint::check(&patch.apply(current), None, rules);
+    if let (Some(files), false) = (files, patch.tracks.is_empty()) {
+        issues.extend(lint::check_track_count(patch.tracks.len(), files, rules));
+    }
+
+    for issue in &issues {
+        println!("{}", issue);
+    }
+    if lint::has_errors(&issues) && !force {
+        anyhow::bail!("Error: lint found errors in new metadata, fix them or use --force")
+    }
+    Ok(())
+}
+
+fn use_color() -> bool {
+    std::io::stdout().is_tty()
+}
+
+/// diff from current tags and covers of `files` to `meta` and `picture`.
+fn diff_files(
+    files: &[Option<String>],
+    patch: &MetaPatch,
+    picture: Option<&Picture>,
+) -> anyhow::Result<MetaDiff> {
+    let tags = TagLib::new(files)?;
+    let current = tags.read()?;
+    let mut diff = MetaDiff::new(&current, &patch.apply(&current));
+    if patch.track_numbers {
+        diff = diff.with_track_numbers(&tags.track_numbers());
+    }
+
+    if picture.is_none() {
+        // covers are not touched
+        return Ok(diff);
+    }
+
+    let covers: Vec<_> = files
+        .iter()
+        .map(|f| {
+            f.as_ref()
+                .and_then(|f| TagLibPicture::new(f).and_then(|p| p.read()).ok())
+        })
+        .collect();
+    Ok(diff.with_covers(&covers, picture))
+}
+
+fn prompt(message: &str) -> anyhow::Result<String> {
+    print!("{}", message);
+    std::io::stdout().flush()?;
+
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
+
+/// cleanup rules from `path`, the default file or built-in rules,
+/// run after `normalize` if given.
+fn load_cleaner(path: Option<&Path>, normalize: Option<Normalization>) -> anyhow::Result<Cleaner> {
+    let default_path = dirs::home_dir().unwrap().join(".music_info_clean.json");
+    let mut result = match path {
+        Some(path) => Cleaner::load(path)?,
+        None if default_path.exists() => Cleaner::load(default_path)?,
+        None => Cleaner::default(),
+    };
+    if let Some(normalize) = normalize {
+        result.normalize_first(normalize);
+    }
+    Ok(result)
+}
+
+/// options of `autotag` on choosing and writing a candidate.
+struct AutotagOptions {
+    yes: bool,
+    threshold: f64,
+    no_picture: bool,
+    cleaner: Option<Cleaner>,
+    locale: Option<Locale>,
+}
+
+fn autotag(
+    providers: Vec<Provider>,
+    terms: Option<SearchTerms>,
+    from_filename: Option<Template>,
+    opt: AutotagOptions,
+    audio: Vec<String>,
+) -> anyhow::Result<()> {
+    let files = audio_files_parser(audio)?;
+    let first_file = files.iter().find_map(|e| e.as_ref()).unwrap().to_owned();
+    let (local, track_numbers) = {
+        let tags = TagLib::new(&files)?;
+        (tags.read()?, tags.track_numbers())
+    };
+
+    let from_filename = from_filename.map(|pattern| filename::draft(&pattern, &files));
+
+    let terms = match terms {
+        Some(terms) => terms,
+        None => from_filename
+            .as_ref()
+            .and_then(SearchTerms::from_metadata)
+            .or_else(|| SearchTerms::from_metadata(&local))
+            .or_else(|| {
+                let path = std::fs::canonicalize(&first_file).ok()?;
+                SearchTerms::from_dir_name(path.parent()?)
+            })
+            .ok_or(anyhow::anyhow!(
+                "Error: could not derive search terms, specify --album"
+            ))?,
+    };
+    println!(
+        "searching for album: {}, artist: {}",
+        terms.album, terms.artist
+    );
+
+    let mut candidates = Vec::new();
+    for provider in providers {
+        let query = terms.to_query(provider);
+        let found = match provider {
+            Provider::MusicBrainz => MusicBrainz::new()
+                .with_locale(opt.locale.clone())
+                .query(&query),
+            Provider::Spotify => spotify_client().and_then(|c| c.query(&query)),
+        };
+        match found {
+            Ok(found) => candidates.extend(found.into_iter().map(|(m, a)| (provider, m, a))),
+            Err(e) => println!("Warning: search on {} failed: {}", provider, e),
+        }
+    }
+    if let Some(cleaner) = &opt.cleaner {
+        for (_, meta, _) in &mut candidates {
+            cleaner.clean(meta);
+        }
+    }
+
+    let ranking = matcher::rank(&local, candidates.iter().map(|x| &x.1));
+    if ranking.is_empty() {
+        anyhow::bail!("Error: no candidate found")
+    }
+
+    let (idx, best) = &ranking[0];
+    let selected = if opt.yes {
+        if best.distance > opt.threshold {
+            anyhow::bail!(
+                "Error: best match distance {:.3} is above threshold {:.3}",
+                best.distance,
+                opt.threshold
+            )
+        }
+        *idx
+    } else {
+        println!("candidates (best match first):");
+        for (n, (idx, m)) in ranking.iter().enumerate().take(AUTOTAG_CANDIDATES) {
+            let (provider, meta, _) = &candidates[*idx];
+            println!(
+                "  {}) [{}] {} / {} ({}), {} tracks, distance {}",
+                n + 1,
+                provider,
+                meta.album,
+                meta.album_artist(),
+                meta.date,
+                meta.tracks.len(),
+                m
+            );
+        }
+
+        let answer = prompt("select candidate [1], or q to quit: ")?;
+        if answer == "q" {
+            return Ok(());
+        }
+        let n = if answer.is_empty() {
+            1
+        } else {
+            usize::from_str(&answer)?
+        };
+        ranking
+            .get(n.wrapping_sub(1))
+            .ok_or(anyhow::anyhow!("Error: no candidate {}", n))?
+            .0
+    };
+
+    let (provider, meta, _) = &candidates[selected];
+    let diff = MetaDiff::new(&local, meta).with_track_numbers(&track_numbers);
+    print!("{}", diff.render(use_color(), true));
+
+    if !opt.yes && prompt("write? [y/N]: ")?.to_lowercase() != "y" {
+        return Ok(());
+    }
+
+    let picture = if opt.no_picture {
+        None
+    } else {
+        match fetch_picture_of(*provider, meta.id.as_deref().unwrap_or_default()) {
+            Ok(pic) => Some(pic),
+            Err(e) => {
+                println!("Warning: could not fetch cover: {}", e);
+                None
+            }
+        }
+    };
+
+    write_files("autotag", files, &MetaPatch::from(meta), picture.as_ref())
+}
+
+fn album_files(album: &Album) -> Vec<Option<String>> {
+    album
+        .files
+        .iter()
+        .map(|f| Some(f.to_string_lossy().into_owned()))
+        .collect()
+}
+
+/// options of `batch` shared by all actions.
+struct BatchOptions {
+    docs: DocumentOptions,
+    cleaner: Option<Cleaner>,
+    locale: Option<Locale>,
+    json: PathBuf,
+    picture: PathBuf,
+    fetch_picture: bool,
+    force: bool,
+}
+
+fn batch_export(album: &Album, opt: &BatchOptions) -> anyhow::Result<Outcome> {
+    let path = album.dir.join(&opt.json);
+    if path.exists() && !opt.force {
+        return Ok(Outcome::Skipped(format!("{} exists", opt.json.display())));
+    }
+
+    let meta = TagLib::new(album_files(album))?.read()?;
+    opt.docs.open(path).write(&meta)?;
+    Ok(Outcome::Done)
+}
+
+/// `net` is held while fetching, so requests to providers are not sent in parallel.
+fn batch_refresh(album: &Album, opt: &BatchOptions, net: &Mutex<()>) -> anyhow::Result<Outcome> {
+    let path = album.dir.join(&opt.json);
+    if !path.exists() {
+        return Ok(Outcome::Skipped(format!("no {}", opt.json.display())));
+    }
+
+    let id = match opt.docs.open(&path).read()?.id {
+        Some(id) => id,
+        None => return Ok(Outcome::Skipped("no stored ID".into())),
+    };
+    let res = ResourceId::from_str(&id)?;
+
+    let (mut meta, picture) = {
+        let _lock = net.lock().unwrap();
+        let picture = opt.fetch_picture.then(|| album.dir.join(&opt.picture));
+        fetch_resource(&res, picture, opt.locale.as_ref())?
+    };
+
+    if meta.tracks.len() != album.files.len() {
+        return Ok(Outcome::Skipped(format!(
+            "{} tracks fetched for {} files",
+            meta.tracks.len(),
+            album.files.len()
+        )));
+    }
+    if meta.id.is_none() {
+        meta.id = Some(id);
+    }
+    if let Some(cleaner) = &opt.cleaner {
+        cleaner.clean(&mut meta);
+    }
+
+    opt.docs.open(&path).write(&meta)?;
+    if let Some((data, path)) = &picture {
+        data.write(path)?;
+    }
+
+    journaled_write(
+        "batch refresh",
+        album_files(album),
+        &MetaPatch::from(&meta),
+        picture.as_ref().map(|p| &p.0),
+    )?;
+    Ok(Outcome::Done)
+}
+
+fn batch_cover(album: &Album, opt: &BatchOptions) -> anyhow::Result<Outcome> {
+    let path = album.dir.join(&opt.picture);
+    if !path.exists() {
+        return Ok(Outcome::Skipped(format!("no {}", opt.picture.display())));
+    }
+
+    let picture = Picture::read(path)?;
+    let patch = MetaPatch {
+        track_numbers: false,
+        ..Default::default()
+    };
+    journaled_write("batch cover", album_files(album), &patch, Some(&picture))?;
+    Ok(Outcome::Done)
+}
+
 fn main() -> anyhow::Result<()> {
     let arg = Cmd::parse();
+    let docs = DocumentOptions {
+        columns: arg.columns.unwrap_or_default(),
+    };
+    let rules = arg.rules.unwrap_or_default();
+    let clean_rules = arg.clean_rules;
+    let normalize = arg.normalize;
+    let locale = arg.locale;
+    let cleaner = match (arg.clean, &normalize) {
+        (true, _) => Some(load_cleaner(clean_rules.as_deref(), normalize.clone())?),
+        (false, Some(n)) => Some(Cleaner::normalizing(n.clone())),
+        (false, None) => None,
+    };
 
     match arg.op {
         Opr::Read {
             json,
             picture,
+            from_filename,
+            image,
+            cue,
+            recursive,
+            order,
             audio,
         } => {
-            let files = audio_files_parser(audio)?;
-            let first_file = files.iter().find_map(|e| e.as_ref()).unwrap().to_owned();
+            let albums = album_inputs(audio, recursive, order)?;
+            let multiple = albums.len() > 1;
+            if multiple && json.is_none() {
+                anyhow::bail!("Error: --json is required to read several albums")
+            }
 
-            let result = TagLib::new(files)?.read()?;
+            for album in albums {
+                let first_file = album
+                    .files
+                    .iter()
+                    .find_map(|e| e.as_ref())
+                    .unwrap()
+                    .to_owned();
 
-            if let Some(path) = json {
-                Json::new(path).write(&result)?;
-            } else {
-                print!("{}", Json::to_string(&result)?);
-            }
+                let result = match &from_filename {
+                    Some(pattern) => filename::draft(pattern, &album.files),
+                    None if image => cue_image(&album, cue.as_ref())?.read()?,
+                    None => TagLib::new(&album.files)?.read()?,
+                };
+
+                if let Some(path) = &json {
+                    docs.open(album.resolve(path, multiple)).write(&result)?;
+                } else {
+                    print!("{}", Json::to_string(&result)?);
+                }
 
-            if let Some(path) = picture {
-                TagLibPicture::new(first_file)?.read()?.write(path)?;
+                if let Some(path) = &picture {
+                    let path = album.resolve(path, multiple);
+                    TagLibPicture::new(first_file)?.read()?.write(path)?;
+                }
             }
         }
         Opr::Write {
             json,
             picture,
+            fields,
+            image,
+            cue,
+            dry_run,
+            force,
+            recursive,
+            order,
             audio,
         } => {
-            let files = audio_files_parser(audio)?;
+            let albums = album_inputs(audio, recursive, order)?;
+            let multiple = albums.len() > 1;
 
-            let meta = Json::new(json).read()?;
+            for album in albums {
+                if let (true, Some(dir)) = (multiple, &album.dir) {
+                    println!("{}:", dir.display());
+                }
 
-            if let Some(path) = picture {
-                let pic = Picture::read(path)?;
+                let mut patch = match &json {
+                    Some(path) => {
+                        let path = album.resolve(path, multiple);
+                        if multiple && !path.exists() {
+                            println!("Warning: {} not found, skipped", path.display());
+                            continue;
+                        }
+                        docs.read_patch(path)?
+                    }
+                    // picture only, files keep their track numbers
+                    None => MetaPatch {
+                        track_numbers: false,
+                        ..Default::default()
+                    },
+                };
+                if !fields.is_empty() {
+                    patch = patch.select(&fields);
+                }
+
+                let pic = match &picture {
+                    Some(path) if fields.is_empty() || fields.contains(&Field::Cover) => {
+                        Some(Picture::read(album.resolve(path, multiple))?)
+                    }
+                    _ => None,
+                };
+
+                if json.is_some() {
+                    let current = if image {
+                        cue_image(&album, cue.as_ref())?.read()?
+                    } else {
+                        TagLib::new(&album.files)?.read()?
+                    };
+                    let files = (!image).then_some(album.files.len());
+                    if let Some(cleaner) = &cleaner {
+                        patch = cleaner.clean_patch(&patch, &current);
+                    }
+                    lint_write(&current, &patch, files, &rules, force || dry_run)?;
+                }
 
-                for file in files.iter().filter_map(|e| e.as_ref()) {
-                    TagLibPicture::new(file)?.write(&pic)?;
+                if image {
+                    let img = cue_image(&album, cue.as_ref())?;
+                    if dry_run {
+                        let current = img.read()?;
+                        let diff = MetaDiff::new(&current, &patch.apply(&current));
+                        print!("{}", diff.render(use_color(), false));
+                    } else {
+                        let entry = Journal::open_default()?.write_image(
+                            "write",
+                            &img,
+                            &patch,
+                            pic.as_ref(),
+                        )?;
+                        println!(
+                            "backup saved as {}, run `undo {}` to restore",
+                            entry.id, entry.id
+                        );
+                    }
+                } else if dry_run {
+                    let diff = diff_files(&album.files, &patch, pic.as_ref())?;
+                    print!("{}", diff.render(use_color(), false));
+                } else {
+                    write_files("write", album.files, &patch, pic.as_ref())?;
                 }
             }
+        }
+        Opr::Clean {
+            json,
+            dry_run,
+            recursive,
+            order,
+            provider,
+            audio,
+        } => {
+            let mut cleaner = load_cleaner(clean_rules.as_deref(), normalize)?;
+            if let Some(provider) = provider {
+                cleaner.assume_provider(provider);
+            }
+            let albums = album_inputs(audio, recursive, order)?;
+            let multiple = albums.len() > 1;
+
+            for album in albums {
+                if let (true, Some(dir)) = (multiple, &album.dir) {
+                    println!("{}:", dir.display());
+                }
+
+                match &json {
+                    Some(path) => {
+                        let path = album.resolve(path, multiple);
+                        if multiple && !path.exists() {
+                            println!("Warning: {} not found, skipped", path.display());
+                            continue;
+                        }
+                        let doc = docs.open(&path);
+                        let current = doc.read()?;
+                        let mut cleaned = current.clone();
+                        cleaner.clean(&mut cleaned);
+
+                        print!(
+                            "{}",
+                            MetaDiff::new(&current, &cleaned).render(use_color(), false)
+                        );
+                        if !dry_run {
+                            doc.write(&cleaned)?;
+                        }
+                    }
+                    None => {
+                        let current = TagLib::new(&album.files)?.read()?;
+                        let keep = MetaPatch {
+                            track_numbers: false,
+                            ..Default::default()
+                        };
+                        let patch = cleaner.clean_patch(&keep, &current);
 
-            TagLib::new(files)?.write(&meta)?;
+                        let diff = MetaDiff::new(&current, &patch.apply(&current));
+                        print!("{}", diff.render(use_color(), false));
+                        if !dry_run && patch != keep {
+                            write_files("clean", album.files, &patch, None)?;
+                        }
+                    }
+                }
+            }
         }
-        Opr::Query { opr } => {
-            let result = match opr {
-                QueryOpr::MusicBrainz { query } => MusicBrainz::new().query(&query)?,
-                QueryOpr::Spotify { query } => {
-                    let default_cred_path = dirs::home_dir().unwrap().join(".spotify_cred.json");
-                    let default_token_path = dirs::home_dir().unwrap().join(".spotify_token.json");
-
-                    Spotify::new(default_cred_path, default_token_path)?.query(&query)?
+        Opr::Lint {
+            json,
+            recursive,
+            order,
+            audio,
+        } => {
+            let albums = album_inputs(audio, recursive, order)?;
+            let multiple = albums.len() > 1;
+            let (mut errors, mut warnings) = (0, 0);
+
+            for album in albums {
+                let meta = match &json {
+                    Some(path) => {
+                        let path = album.resolve(path, multiple);
+                        if multiple && !path.exists() {
+                            println!("Warning: {} not found, skipped", path.display());
+                            continue;
+                        }
+                        docs.open(path).read()?
+                    }
+                    None => TagLib::new(&album.files)?.read()?,
+                };
+
+                let issues = lint::check(&meta, Some(album.files.len()), &rules);
+                if let (true, false, Some(dir)) = (multiple, issues.is_empty(), &album.dir) {
+                    println!("{}:", dir.display());
                 }
+                for issue in &issues {
+                    println!("{}", issue);
+                    match issue.severity {
+                        Severity::Error => errors += 1,
+                        _ => warnings += 1,
+                    }
+                }
+            }
+
+            println!("{} errors, {} warnings", errors, warnings);
+            if errors > 0 {
+                anyhow::bail!("Error: lint found {} errors", errors)
+            }
+        }
+        Opr::Diff {
+            picture,
+            verbose,
+            json,
+            current,
+        } => {
+            let pic = picture.map(Picture::read).transpose()?;
+
+            let is_document = current.len() == 1 && fileio::is_meta_document(&current[0]);
+
+            let diff = if is_document {
+                MetaDiff::new(&docs.open(&current[0]).read()?, &docs.open(json).read()?)
+            } else {
+                let patch = docs.read_patch(json)?;
+                diff_files(&audio_files_parser(current)?, &patch, pic.as_ref())?
+            };
+            print!("{}", diff.render(use_color(), verbose));
+        }
+        Opr::Query {
+            match_audio,
+            pick,
+            output,
+            picture,
+            write,
+            opr,
+        } => {
+            let provider = match opr {
+                QueryOpr::Spotify { .. } => Provider::Spotify,
+                _ => Provider::MusicBrainz,
             };
 
-            let last_idx = result.len() - 1;
-            println!("query result:");
-            for (idx, (meta, add_info)) in result.iter().enumerate() {
-                print!("{}", meta);
+            let mut result = match opr {
+                QueryOpr::MusicBrainz { query } => {
+                    MusicBrainz::new().with_locale(locale).query(&query)?
+                }
+                QueryOpr::Spotify { query } => spotify_client()?.query(&query)?,
+                QueryOpr::Discid {
+                    cue,
+                    log,
+                    offsets,
+                    leadout,
+                } => {
+                    let toc = if let Some(cue) = cue {
+                        Toc::from_cue(cue, leadout)?
+                    } else if let Some(log) = log {
+                        Toc::from_log(log)?
+                    } else if !offsets.is_empty() {
+                        Toc::new(offsets, leadout.unwrap())?
+                    } else {
+                        anyhow::bail!("Error: one of --cue, --log or --offsets is required")
+                    };
 
-                if add_info.len() > 0 {
-                    println!("additional info:")
+                    println!("disc ID: {}", toc.disc_id());
+                    MusicBrainz::new().with_locale(locale).lookup_toc(&toc)?
                 }
-                for (k, v) in add_info {
-                    println!("  {}: {}", k, v);
+                QueryOpr::Acoustid {
+                    key,
+                    base_url,
+                    limit,
+                    audio,
+                } => {
+                    let key = match key {
+                        Some(k) => k,
+                        None => {
+                            let path = dirs::home_dir().unwrap().join(".acoustid_key");
+                            std::fs::read_to_string(&path)
+                                .map_err(|_| {
+                                    anyhow::anyhow!(
+                                        "Error: acoustid API key not given and {} not found",
+                                        path.display()
+                                    )
+                                })?
+                                .trim()
+                                .to_string()
+                        }
+                    };
+                    let client = match base_url {
+                        Some(url) => AcoustId::with_base_url(key, url),
+                        None => AcoustId::new(key),
+                    };
+
+                    let files = audio_files_parser(audio)?;
+                    let fps = Fingerprint::compute_all(&files)?;
+                    let file_count = fps.iter().flatten().count();
+
+                    let mb = MusicBrainz::new().with_locale(locale);
+                    let mut result = Vec::new();
+                    for cand in client.candidate_releases(&fps)?.into_iter().take(limit) {
+                        let (meta, mut add_info) = mb.fetch_all(&cand.release_id)?;
+                        add_info.insert(
+                            0,
+                            (
+                                "acoustid".into(),
+                                format!(
+                                    "matched files: {}/{}, score: {:.2}",
+                                    cand.matched_files, file_count, cand.score
+                                ),
+                            ),
+                        );
+                        result.push((meta, add_info));
+                    }
+                    result
+                }
+            };
+
+            if let Some(cleaner) = &cleaner {
+                for (meta, _) in &mut result {
+                    cleaner.clean(meta);
+                }
+            }
+
+            let files = if match_audio.is_empty() {
+                None
+            } else {
+                Some(audio_files_parser(match_audio)?)
+            };
+            let result = match &files {
+                Some(files) => rank_candidates(&TagLib::new(files)?.read()?, result),
+                None => result,
+            };
+
+            if !pick {
+                let header = if files.is_some() {
+                    "query result (best match first):"
+                } else {
+                    "query result:"
+                };
+                print_candidates(header, &result);
+                return Ok(());
+            }
+
+            let selected = match picker::pick(&result)? {
+                Some(idx) => &result[idx].0,
+                None => return Ok(()),
+            };
+            let pic = picture
+                .as_ref()
+                .map(|_| fetch_picture_of(provider, selected.id.as_deref().unwrap_or_default()))
+                .transpose()?;
+
+            if write {
+                write_files(
+                    "query --write",
+                    files.unwrap(),
+                    &MetaPatch::from(selected),
+                    pic.as_ref(),
+                )?;
+            } else if let Some(out) = output {
+                docs.open(out).write(selected)?;
+            } else {
+                print!("{}", Json::to_string(selected)?);
+            }
+
+            if let (Some(data), Some(path)) = (pic, picture) {
+                data.write(path)?;
+            }
+        }
+        Opr::Lookup { code } => {
+            let mut result = Vec::new();
+
+            for (provider, found) in [
+                (
+                    Provider::MusicBrainz,
+                    MusicBrainz::new().with_locale(locale).lookup(&code),
+                ),
+                (
+                    Provider::Spotify,
+                    spotify_client().and_then(|c| c.lookup(&code)),
+                ),
+            ] {
+                match found {
+                    Ok(found) => {
+                        result.extend(found.into_iter().map(|(meta, mut add_info)| {
+                            add_info.insert(0, ("provider".into(), provider.to_string()));
+                            (meta, add_info)
+                        }));
+                    }
+                    Err(e) => println!("Warning: lookup on {} failed: {}", provider, e),
                 }
+            }
 
-                if idx != last_idx {
-                    print!("\n\n")
+            if let Some(cleaner) = &cleaner {
+                for (meta, _) in &mut result {
+                    cleaner.clean(meta);
                 }
             }
+            print_candidates("lookup result:", &result);
         }
         Opr::Fetch { opr } => {
-            let (result, output, picture) = match opr {
+            let (res, output, picture) = match opr {
                 FetchOpr::MusicBrainz {
                     output,
                     picture,
                     id,
-                } => {
-                    let client = MusicBrainz::new();
-                    let result = client.fetch(&id)?;
-
-                    let picture = picture
-                        .map(|path| client.fetch_picture(&id).map(|data| (data, path)))
-                        .transpose()?;
-
-                    (result, output, picture)
-                }
+                } => (
+                    ResourceId::parse_for(&id, Provider::MusicBrainz)?,
+                    output,
+                    picture,
+                ),
                 FetchOpr::Spotify {
                     output,
                     picture,
                     id,
-                } => {
-                    let default_cred_path = dirs::home_dir().unwrap().join(".spotify_cred.json");
-                    let default_token_path = dirs::home_dir().unwrap().join(".spotify_token.json");
-
-                    let client = Spotify::new(default_cred_path, default_token_path)?;
-                    let result = client.fetch(&id)?;
-
-                    let picture = picture
-                        .map(|path| client.fetch_picture(&id).map(|data| (data, path)))
-                        .transpose()?;
-
-                    (result, output, picture)
-                }
+                } => (
+                    ResourceId::parse_for(&id, Provider::Spotify)?,
+                    output,
+                    picture,
+                ),
+                FetchOpr::Auto {
+                    output,
+                    picture,
+                    url,
+                } => (ResourceId::from_str(&url)?, output, picture),
             };
 
+            let (mut result, picture) = fetch_resource(&res, picture, locale.as_ref())?;
+            if let Some(cleaner) = &cleaner {
+                cleaner.clean(&mut result);
+            }
+
             if let Some(out) = output {
-                Json::new(out).write(&result)?;
+                docs.open(out).write(&result)?;
             } else {
                 print!("{}", Json::to_string(&result)?);
             }
@@ -244,6 +1487,135 @@ fn main() -> anyhow::Result<()> {
                 data.write(path)?;
             }
         }
+        Opr::Autotag {
+            providers,
+            album,
+            artist,
+            from_filename,
+            yes,
+            threshold,
+            no_picture,
+            audio,
+        } => {
+            let terms = album.map(|album| SearchTerms::new(album, artist.unwrap_or_default()));
+            autotag(
+                providers,
+                terms,
+                from_filename,
+                AutotagOptions {
+                    yes,
+                    threshold,
+                    no_picture,
+                    cleaner,
+                    locale,
+                },
+                audio,
+            )?
+        }
+        Opr::Rename {
+            template,
+            dest,
+            json,
+            fs,
+            dry_run,
+            recursive,
+            order,
+            audio,
+        } => {
+            let albums = album_inputs(audio, recursive, order)?;
+            let multiple = albums.len() > 1;
+
+            for album in albums {
+                let meta = match &json {
+                    Some(path) => docs.open(album.resolve(path, multiple)).read()?,
+                    None => TagLib::new(&album.files)?.read()?,
+                };
+
+                let plan = rename::plan(&meta, &album.files, &template, &dest, fs)?;
+                for m in plan.moves() {
+                    println!("{}", m);
+                }
+                if !dry_run {
+                    plan.execute()?;
+                }
+            }
+        }
+        Opr::Batch {
+            action,
+            root,
+            json,
+            picture,
+            fetch_picture,
+            force,
+            order,
+            jobs,
+            state,
+        } => {
+            let albums = scan_dir(&root, true, order)?;
+            let state = state
+                .map(|path| batch::State::open(path, action, &root))
+                .transpose()?;
+            let jobs = jobs.unwrap_or_else(|| {
+                std::thread::available_parallelism()
+                    .map(|n| n.get())
+                    .unwrap_or(1)
+            });
+            println!("{} albums found under {}", albums.len(), root.display());
+
+            let opt = BatchOptions {
+                docs,
+                cleaner,
+                locale,
+                json,
+                picture,
+                fetch_picture,
+                force,
+            };
+            let net = Mutex::new(());
+            let report = batch::run(&albums, jobs, state, |album| match action {
+                Action::Export => batch_export(album, &opt),
+                Action::Refresh => batch_refresh(album, &opt, &net),
+                Action::Cover => batch_cover(album, &opt),
+            });
+
+            print!("\n{}", report);
+            if !report.failed.is_empty() {
+                anyhow::bail!("Error: {} albums failed", report.failed.len())
+            }
+        }
+        Opr::History { count, prune } => {
+            let journal = Journal::open_default()?;
+            if let Some(keep) = prune {
+                let removed = journal.prune(keep)?;
+                println!("removed {} entries.", removed);
+            }
+
+            let entries = journal.list()?;
+            if entries.is_empty() {
+                println!("no history.");
+            }
+            for entry in entries.iter().rev().take(count) {
+                println!("{}", entry);
+            }
+        }
+        Opr::Undo { id } => {
+            let journal = Journal::open_default()?;
+            let entry = match id {
+                Some(id) => journal.get(&id)?,
+                None => journal
+                    .list()?
+                    .pop()
+                    .ok_or(anyhow::anyhow!("Error: no history to undo"))?,
+            };
+
+            let backup = journal.restore(&entry)?;
+            println!(
+                "restored {} files to the state before {}",
+                entry.files.len(),
+                entry
+            );
+            println!("state before undo saved as {}", backup.id);
+        }
         Opr::Template { opr } => {
             let mut default = Metadata::default();
             default.tracks = vec![Track::default()];
@@ -251,13 +1623,21 @@ fn main() -> anyhow::Result<()> {
             match opr {
                 TempOpr::Json { output } => {
                     if let Some(path) = output {
-                        Json::new(path).write(&default)?;
+                        docs.open(path).write(&default)?;
                     } else {
                         print!("{}", Json::to_string(&default)?);
                     }
                 }
             }
         }
+        Opr::Schema { output } => {
+            let schema = serde_json::to_string_pretty(&schema::json_schema())?;
+            if let Some(path) = output {
+                std::fs::write(path, schema)?;
+            } else {
+                println!("{}", schema);
+            }
+        }
     };
 
     Ok(())
@@ -0,0 +1,274 @@
+use std::fmt;
+
+use crate::info_struct::{Metadata, Track};
+
+const ALBUM_WEIGHT: f64 = 3.0;
+const DATE_WEIGHT: f64 = 1.0;
+const TRACK_COUNT_WEIGHT: f64 = 2.0;
+const TITLE_WEIGHT: f64 = 3.0;
+const ARTIST_WEIGHT: f64 = 2.0;
+const LENGTH_WEIGHT: f64 = 2.0;
+
+/// length difference in seconds treated as identical
+const LENGTH_GRACE: f64 = 2.0;
+/// length difference in seconds treated as completely different
+const LENGTH_MAX: f64 = 30.0;
+
+/// result of comparing local metadata with a candidate.
+/// distances are in 0.0 (identical) ..= 1.0 (completely different).
+#[derive(Debug, Clone)]
+pub struct Match {
+    pub distance: f64,
+    /// distance of each compared field, fields without local data are omitted
+    pub fields: Vec<(&'static str, f64)>,
+    /// track numbers (1-origin) in candidate which have no local file
+    pub missing_tracks: Vec<usize>,
+    /// track numbers (1-origin) of local files which have no candidate track
+    pub extra_tracks: Vec<usize>,
+}
+
+impl fmt::Display for Match {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fields = self
+            .fields
+            .iter()
+            .map(|(k, v)| format!("{}: {:.2}", k, v))
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(f, "{:.3} ({})", self.distance, fields)?;
+
+        if !self.missing_tracks.is_empty() {
+            write!(
+                f,
+                ", missing tracks: {}",
+                join_numbers(&self.missing_tracks)
+            )?;
+        }
+        if !self.extra_tracks.is_empty() {
+            write!(f, ", extra tracks: {}", join_numbers(&self.extra_tracks))?;
+        }
+        Ok(())
+    }
+}
+
+fn join_numbers(nums: &[usize]) -> String {
+    nums.iter()
+        .map(|n| n.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn normalize(s: &str) -> Vec<char> {
+    s.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+        .chars()
+        .collect()
+}
+
+/// normalized levenshtein distance between two strings, ignoring case and repeated whitespaces.
+pub fn string_distance(a: &str, b: &str) -> f64 {
+    let a = normalize(a);
+    let b = normalize(b);
+    if a == b {
+        return 0.0;
+    }
+
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+
+    for (i, ca) in a.iter().enumerate() {
+        cur[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+
+    prev[b.len()] as f64 / a.len().max(b.len()) as f64
+}
+
+fn length_distance(a: u32, b: u32) -> f64 {
+    let diff = (a as f64 - b as f64).abs();
+    ((diff - LENGTH_GRACE) / (LENGTH_MAX - LENGTH_GRACE)).clamp(0.0, 1.0)
+}
+
+fn is_blank(track: &Track) -> bool {
+    track.title.is_empty() && track.artist.is_empty() && track.length.is_none()
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.iter().sum::<f64>() / values.len() as f64)
+    }
+}
+
+/// compare `local` (usually read from audio files) with `candidate` fetched from a provider.
+pub fn compare(local: &Metadata, candidate: &Metadata) -> Match {
+    let mut fields = Vec::new();
+    let mut weighted = Vec::new();
+
+    if !local.album.is_empty() {
+        let d = string_distance(&local.album, &candidate.album);
+        fields.push(("album", d));
+        weighted.push((d, ALBUM_WEIGHT));
+    }
+
+    if local.date != 0 {
+        let d = if local.date == candidate.date {
+            0.0
+        } else {
+            1.0
+        };
+        fields.push(("date", d));
+        weighted.push((d, DATE_WEIGHT));
+    }
+
+    let local_count = local.tracks.len();
+    let candidate_count = candidate.tracks.len();
+    let count_d = if local_count == candidate_count {
+        0.0
+    } else {
+        local_count.abs_diff(candidate_count) as f64 / local_count.max(candidate_count) as f64
+    };
+    fields.push(("track count", count_d));
+    weighted.push((count_d, TRACK_COUNT_WEIGHT));
+
+    let mut titles = Vec::new();
+    let mut artists = Vec::new();
+    let mut lengths = Vec::new();
+    let mut missing_tracks = Vec::new();
+
+    for (i, cand) in candidate.tracks.iter().enumerate() {
+        let loc = match local.tracks.get(i) {
+            Some(t) if !is_blank(t) => t,
+            _ => {
+                missing_tracks.push(i + 1);
+                continue;
+            }
+        };
+
+        if !loc.title.is_empty() {
+            titles.push(string_distance(&loc.title, &cand.title));
+        }
+        if !loc.artist.is_empty() {
+            artists.push(string_distance(&loc.artist, &cand.artist));
+        }
+        if let (Some(a), Some(b)) = (loc.length, cand.length) {
+            lengths.push(length_distance(a, b));
+        }
+    }
+
+    let extra_tracks = (candidate_count + 1..=local_count)
+        .filter(|n| !is_blank(&local.tracks[n - 1]))
+        .collect();
+
+    for (name, values, weight) in [
+        ("titles", titles, TITLE_WEIGHT),
+        ("artists", artists, ARTIST_WEIGHT),
+        ("lengths", lengths, LENGTH_WEIGHT),
+    ] {
+        if let Some(d) = mean(&values) {
+            fields.push((name, d));
+            weighted.push((d, weight));
+        }
+    }
+
+    let total_weight: f64 = weighted.iter().map(|(_, w)| w).sum();
+    let distance = weighted.iter().map(|(d, w)| d * w).sum::<f64>() / total_weight;
+
+    Match {
+        distance,
+        fields,
+        missing_tracks,
+        extra_tracks,
+    }
+}
+
+/// compare `local` with every candidate and return indexes of `candidates` with their match,
+/// sorted from the best match.
+pub fn rank<'a, I>(local: &Metadata, candidates: I) -> Vec<(usize, Match)>
+where
+    I: IntoIterator<Item = &'a Metadata>,
+{
+    let mut result: Vec<_> = candidates
+        .into_iter()
+        .map(|c| compare(local, c))
+        .enumerate()
+        .collect();
+    result.sort_by(|a, b| a.1.distance.total_cmp(&b.1.distance));
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn album(title: &str, tracks: &[(&str, Option<u32>)]) -> Metadata {
+        let tracks = tracks
+            .iter()
+            .map(|(title, length)| {
+                let mut track = Track::new(*title, "Artist");
+                track.length = *length;
+                track
+            })
+            .collect();
+        Metadata::new(None, title, 2000, "", tracks)
+    }
+
+    #[test]
+    fn tracklist_outweighs_album_title() {
+        let local = album(
+            "Greatest Hits",
+            &[("One", Some(200)), ("Two", Some(180)), ("Three", Some(240))],
+        );
+        let same_title = album(
+            "Greatest Hits",
+            &[
+                ("Intro", Some(60)),
+                ("Other", Some(300)),
+                ("Songs", Some(120)),
+                ("Here", Some(200)),
+                ("Outro", Some(90)),
+            ],
+        );
+        let same_tracks = album(
+            "Greatest Hits (Deluxe Remastered)",
+            &[("One", Some(201)), ("Two", Some(179)), ("Three", Some(240))],
+        );
+
+        let result = rank(&local, [&same_title, &same_tracks]);
+        assert_eq!(result[0].0, 1);
+        assert!(result[0].1.distance < result[1].1.distance);
+        assert_eq!(result[1].1.missing_tracks, vec![4, 5]);
+    }
+
+    #[test]
+    fn empty_and_missing_values_are_finite() {
+        let empty = album("", &[]);
+        let full = album("Album", &[("One", Some(200)), ("Two", None)]);
+        let no_lengths = album("Album", &[("One", None), ("Two", None)]);
+
+        for (local, candidate) in [
+            (&empty, &empty),
+            (&empty, &full),
+            (&full, &empty),
+            (&no_lengths, &full),
+        ] {
+            let m = compare(local, candidate);
+            assert!(m.distance.is_finite() && (0.0..=1.0).contains(&m.distance));
+        }
+
+        assert_eq!(compare(&empty, &empty).distance, 0.0);
+        assert_eq!(compare(&empty, &full).missing_tracks, vec![1, 2]);
+        assert_eq!(compare(&full, &empty).extra_tracks, vec![1, 2]);
+        let fields = compare(&no_lengths, &full).fields;
+        assert!(fields.iter().all(|(name, _)| *name != "lengths"));
+        assert_eq!(string_distance("", ""), 0.0);
+        assert_eq!(string_distance("", "abc"), 1.0);
+    }
+}
@@ -1,5 +1,14 @@
 pub mod oauth2;
 
+pub mod resolve;
+pub use resolve::{Entity, Provider, ResourceId};
+
+pub mod locale;
+pub use locale::Locale;
+
+pub mod acoust_id;
+pub use acoust_id::AcoustId;
+
 pub mod music_brainz;
 pub use music_brainz::MusicBrainz;
 
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+use crate::{fingerprint::Fingerprint, net};
+
+mod inner_structs;
+
+const DEFAULT_BASE_URL: &str = "https://api.acoustid.org";
+
+/// MusicBrainz recording matched by a fingerprint.
+#[derive(Debug, Clone)]
+pub struct RecordingMatch {
+    pub recording_id: String,
+    pub score: f64,
+    pub release_ids: Vec<String>,
+}
+
+/// release which contains recordings matched by fingerprints.
+#[derive(Debug, Clone)]
+pub struct ReleaseCandidate {
+    pub release_id: String,
+    /// number of files which have a recording in this release
+    pub matched_files: usize,
+    /// sum of the best score of each matched file
+    pub score: f64,
+}
+
+pub struct AcoustId {
+    client: net::Client,
+    api_key: String,
+    base_url: String,
+}
+
+impl AcoustId {
+    pub fn new<S: Into<String>>(api_key: S) -> AcoustId {
+        AcoustId::with_base_url(api_key, DEFAULT_BASE_URL)
+    }
+
+    /// use `base_url` instead of the public AcoustID server, e.g. a local mirror.
+    pub fn with_base_url<S: Into<String>, U: Into<String>>(api_key: S, base_url: U) -> AcoustId {
+        let base_url: String = base_url.into();
+
+        AcoustId {
+            client: net::http_client(),
+            api_key: api_key.into(),
+            base_url: base_url.trim_end_matches('/').into(),
+        }
+    }
+
+    /// find MusicBrainz recordings of `fp`.
+    pub fn lookup(&self, fp: &Fingerprint) -> anyhow::Result<Vec<RecordingMatch>> {
+        let duration = format!("{}", fp.duration.round() as u64);
+        let resp = self
+            .client
+            .post(&format!("{}/v2/lookup", self.base_url))
+            .send_form(&[
+                ("client", self.api_key.as_str()),
+                ("meta", "recordings releaseids"),
+                ("duration", duration.as_str()),
+                ("fingerprint", fp.fingerprint.as_str()),
+            ]);
+
+        let lookup: inner_structs::Lookup = match resp {
+            Ok(r) => r.into_json()?,
+            Err(ureq::Error::Status(c, r)) => {
+                anyhow::bail!(
+                    "Failed to query for acoustid: http {}: {}",
+                    c,
+                    r.into_string().unwrap_or_default()
+                )
+            }
+            Err(ureq::Error::Transport(_)) => {
+                anyhow::bail!("Http transport error")
+            }
+        };
+
+        if lookup.status != "ok" {
+            anyhow::bail!("Failed to query for acoustid: status {}", lookup.status)
+        }
+
+        let result = lookup
+            .results
+            .into_iter()
+            .flat_map(|r| {
+                let score = r.score;
+                r.recordings.into_iter().map(move |rec| RecordingMatch {
+                    recording_id: rec.id,
+                    score,
+                    release_ids: rec.releases.into_iter().map(|x| x.id).collect(),
+                })
+            })
+            .collect();
+
+        Ok(result)
+    }
+
+    /// look up every fingerprint and aggregate matched recordings into releases,
+    /// sorted by the number of matched files and score.
+    pub fn candidate_releases(
+        &self,
+        fps: &[Option<Fingerprint>],
+    ) -> anyhow::Result<Vec<ReleaseCandidate>> {
+        let mut releases: HashMap<String, ReleaseCandidate> = HashMap::new();
+
+        for fp in fps.iter().flatten() {
+            // best score of this file for each release
+            let mut best: HashMap<String, f64> = HashMap::new();
+            for rec in self.lookup(fp)? {
+                for release in rec.release_ids {
+                    let score = best.entry(release).or_insert(0.0);
+                    *score = score.max(rec.score);
+                }
+            }
+
+            for (release_id, score) in best {
+                let entry = releases
+                    .entry(release_id.clone())
+                    .or_insert(ReleaseCandidate {
+                        release_id,
+                        matched_files: 0,
+                        score: 0.0,
+                    });
+                entry.matched_files += 1;
+                entry.score += score;
+            }
+        }
+
+        let mut result: Vec<_> = releases.into_values().collect();
+        result.sort_by(|a, b| {
+            b.matched_files
+                .cmp(&a.matched_files)
+                .then(b.score.total_cmp(&a.score))
+        });
+        Ok(result)
+    }
+}
@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReleaseRef {
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Recording {
+    pub id: String,
+    #[serde(default)]
+    pub releases: Vec<ReleaseRef>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LookupResult {
+    pub id: String,
+    pub score: f64,
+    #[serde(default)]
+    pub recordings: Vec<Recording>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Lookup {
+    pub status: String,
+    #[serde(default)]
+    pub results: Vec<LookupResult>,
+}
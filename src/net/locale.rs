@@ -0,0 +1,148 @@
+use std::{fmt, str::FromStr};
+
+/// ISO 639-1 codes of common languages with their ISO 639-3 codes, as used by MusicBrainz
+/// in text representations of releases
+const LANGUAGES: [(&str, &str); 12] = [
+    ("en", "eng"),
+    ("ja", "jpn"),
+    ("zh", "zho"),
+    ("ko", "kor"),
+    ("de", "deu"),
+    ("fr", "fra"),
+    ("es", "spa"),
+    ("it", "ita"),
+    ("pt", "por"),
+    ("ru", "rus"),
+    ("nl", "nld"),
+    ("sv", "swe"),
+];
+
+/// language as ISO 639-1 code if known, like `jpn` and `ja_JP` to `ja`.
+fn language_code(code: &str) -> String {
+    let code = code
+        .split(['_', '-'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|(_, long)| *long == code)
+        .map_or(code, |(short, _)| short.to_string())
+}
+
+/// script as ISO 15924 code, like `latn` to `Latn`.
+fn script_code(code: &str) -> String {
+    let mut chars = code.chars();
+    match chars.next() {
+        Some(first) => first
+            .to_uppercase()
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect(),
+        None => String::new(),
+    }
+}
+
+/// true if letters of `value` are written in `script`. unknown scripts match anything.
+fn is_in_script(value: &str, script: &str) -> bool {
+    let kana = |c: char| ('\u{3041}'..='\u{30FF}').contains(&c);
+    let han =
+        |c: char| ('\u{4E00}'..='\u{9FFF}').contains(&c) || ('\u{3400}'..='\u{4DBF}').contains(&c);
+    let hangul = |c: char| ('\u{AC00}'..='\u{D7AF}').contains(&c);
+
+    let mut letters = value.chars().filter(|c| c.is_alphabetic()).peekable();
+    if letters.peek().is_none() {
+        return true;
+    }
+    match script {
+        "Latn" => letters.all(|c| c <= '\u{024F}'),
+        "Cyrl" => letters.all(|c| ('\u{0400}'..='\u{04FF}').contains(&c)),
+        "Jpan" | "Hrkt" | "Hira" | "Kana" => letters.any(kana),
+        "Hani" | "Hans" | "Hant" => letters.all(han),
+        "Kore" | "Hang" => letters.any(hangul),
+        _ => true,
+    }
+}
+
+/// preferred language and script of names, like `en`, `ja-Latn` or `Latn`.
+/// languages are ISO 639 codes, scripts are ISO 15924 codes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Locale {
+    pub language: Option<String>,
+    pub script: Option<String>,
+}
+
+impl Locale {
+    pub fn new(language: Option<&str>, script: Option<&str>) -> Locale {
+        Locale {
+            language: language.filter(|l| !l.is_empty()).map(language_code),
+            script: script.filter(|s| !s.is_empty()).map(script_code),
+        }
+    }
+
+    fn language_matches(&self, language: Option<&str>) -> bool {
+        match (&self.language, language) {
+            (Some(want), Some(have)) => *want == language_code(have),
+            (Some(_), None) => false,
+            (None, _) => true,
+        }
+    }
+
+    /// true if `language` and `script` satisfy this preference, where None is unknown.
+    pub fn matches(&self, language: Option<&str>, script: Option<&str>) -> bool {
+        let script_ok = match (&self.script, script) {
+            (Some(want), Some(have)) => *want == script_code(have),
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        self.language_matches(language) && script_ok
+    }
+
+    /// true if `name` is written in the preferred script, or no script is preferred.
+    pub fn is_written_in(&self, name: &str) -> bool {
+        match &self.script {
+            Some(script) => is_in_script(name, script),
+            None => true,
+        }
+    }
+
+    /// true if an alias `name` in `locale` satisfies this preference.
+    pub fn accepts_alias(&self, locale: Option<&str>, name: &str) -> bool {
+        self.language_matches(locale) && self.is_written_in(name)
+    }
+}
+
+impl FromStr for Locale {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Locale> {
+        let mut language = None;
+        let mut script = None;
+        for part in s.split(['-', '_']).filter(|p| !p.is_empty()) {
+            if !part.chars().all(|c| c.is_ascii_alphabetic()) {
+                anyhow::bail!("Error: invalid locale: {}", s)
+            }
+            match part.len() {
+                2 | 3 if language.is_none() && script.is_none() => language = Some(part),
+                4 if script.is_none() => script = Some(part),
+                // region, as in `ja_JP`
+                2 if language.is_some() => (),
+                _ => anyhow::bail!("Error: invalid locale: {}", s),
+            }
+        }
+        if language.is_none() && script.is_none() {
+            anyhow::bail!("Error: invalid locale: {}", s)
+        }
+        Ok(Locale::new(language, script))
+    }
+}
+
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.language, &self.script) {
+            (Some(language), Some(script)) => write!(f, "{}-{}", language, script),
+            (Some(language), None) => write!(f, "{}", language),
+            (None, Some(script)) => write!(f, "{}", script),
+            (None, None) => Ok(()),
+        }
+    }
+}
@@ -3,21 +3,44 @@ use std::{io::Read, str::FromStr};
 use crate::{
     fileio::picture::Picture,
     info_struct::*,
-    net,
+    net::{self, Entity, Locale, Provider, ResourceId},
+    sort_name,
+    toc::Toc,
     traits::{FetchMeta, FetchPicture},
 };
 
 mod inner_structs;
 
+/// locale of aliases whose sort names are readings of Japanese names
+const READING_LOCALE: &str = "ja";
+
+/// relationship from a release to its pseudo-releases with translated or transliterated titles
+const TRANSLATION_RELATION: &str = "transl-tracklisting";
+
+/// alias type which is not a name, only used for search
+const SEARCH_HINT: &str = "Search hint";
+
 pub struct MusicBrainz {
     client: net::Client,
+    /// preferred locale of titles and artist names, original names if None
+    locale: Option<Locale>,
 }
 
 impl MusicBrainz {
     pub fn new() -> MusicBrainz {
         let client = net::http_client();
 
-        MusicBrainz { client }
+        MusicBrainz {
+            client,
+            locale: None,
+        }
+    }
+
+    /// take titles from pseudo-releases and artist names from aliases in `locale`,
+    /// falling back to the original names.
+    pub fn with_locale(mut self, locale: Option<Locale>) -> MusicBrainz {
+        self.locale = locale;
+        self
     }
 
     fn get_mb(
@@ -59,6 +82,181 @@ impl MusicBrainz {
             }
         }
     }
+
+    /// resolve `res` to a release MBID.
+    /// release groups are resolved to their earliest official release.
+    pub fn release_id(&self, res: &ResourceId) -> anyhow::Result<String> {
+        if res.provider != Provider::MusicBrainz {
+            anyhow::bail!("Error: {} is not a musicbrainz ID", res.id)
+        }
+
+        match res.entity {
+            Entity::Release => Ok(res.id.clone()),
+            Entity::ReleaseGroup => {
+                let json = self.get_mb(
+                    &format!("http://musicbrainz.org/ws/2/release-group/{}", res.id),
+                    &[("inc", "releases")],
+                    0,
+                )?;
+                let group: inner_structs::ReleaseGroup = serde_json::from_value(json)?;
+
+                let earliest = |official_only: bool| {
+                    group
+                        .releases
+                        .iter()
+                        .filter(|r| {
+                            !official_only || r.status.as_deref().unwrap_or_default() == "Official"
+                        })
+                        .min_by_key(|r| match r.date.as_deref() {
+                            Some(d) if !d.is_empty() => d.to_string(),
+                            _ => "9999".to_string(),
+                        })
+                        .map(|r| r.id.clone())
+                };
+
+                earliest(true)
+                    .or_else(|| earliest(false))
+                    .ok_or(anyhow::anyhow!(
+                        "Error: release group {} has no release",
+                        res.id
+                    ))
+            }
+            Entity::Track => anyhow::bail!("Error: musicbrainz track ID is not supported"),
+        }
+    }
+
+    /// find releases by disc ID of `toc`, falling back to fuzzy toc lookup.
+    pub fn lookup_toc(&self, toc: &Toc) -> anyhow::Result<Vec<(Metadata, AddInfo)>> {
+        let json = self.get_mb(
+            &format!("http://musicbrainz.org/ws/2/discid/{}", toc.disc_id()),
+            &[("toc", &toc.to_query()), ("cdstubs", "no")],
+            0,
+        )?;
+
+        let release_ids = json["releases"]
+            .as_array()
+            .map(|a| {
+                a.iter()
+                    .filter_map(|e| e["id"].as_str())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let mut result = Vec::with_capacity(release_ids.len());
+
+        for release in release_ids {
+            result.push(self.fetch_all(release)?);
+        }
+
+        Ok(result)
+    }
+
+    /// titles of a pseudo-release of `release` in the preferred locale,
+    /// None if `release` is already in it or has no such pseudo-release, warning if `warn`.
+    fn localized_tracklist(
+        &self,
+        release: &inner_structs::Release,
+        warn: bool,
+    ) -> anyhow::Result<Option<inner_structs::Tracklist>> {
+        let locale = match &self.locale {
+            Some(l) => l,
+            None => return Ok(None),
+        };
+        let (language, script) = text_locale(&release.text_representation);
+        if locale.matches(language, script) {
+            return Ok(None);
+        }
+
+        let pseudo = release
+            .relations
+            .iter()
+            .filter(|r| r.kind == TRANSLATION_RELATION)
+            .filter_map(|r| r.release.as_ref())
+            .find(|r| {
+                let (language, script) = text_locale(&r.text_representation);
+                locale.matches(language, script)
+            });
+
+        match pseudo {
+            Some(pseudo) => {
+                let json = self.get_mb(
+                    &format!("http://musicbrainz.org/ws/2/release/{}", pseudo.id),
+                    &[("inc", "recordings")],
+                    0,
+                )?;
+                Ok(Some(serde_json::from_value(json)?))
+            }
+            None => {
+                if warn {
+                    println!(
+                        "Warning: release {} has no tracklist in {}, using original titles",
+                        release.id, locale
+                    );
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    /// name of a credited artist in the preferred locale: an alias in it, primary aliases first,
+    /// or the credited name. with the locale of the name if it is in the preferred one.
+    fn credit_name(&self, credit: &inner_structs::ArtistCredit) -> (String, Option<Locale>) {
+        let (locale, artist) = match (&self.locale, &credit.artist) {
+            (Some(l), Some(a)) => (l, a),
+            _ => return (credit.name.clone(), None),
+        };
+        if locale.language.is_none() && locale.is_written_in(&credit.name) {
+            return (credit.name.clone(), Some(locale.clone()));
+        }
+
+        let mut aliases: Vec<&inner_structs::Alias> = artist
+            .aliases
+            .iter()
+            .filter(|a| a.kind.as_deref() != Some(SEARCH_HINT))
+            .filter(|a| locale.accepts_alias(a.locale.as_deref(), &a.name))
+            .collect();
+        aliases.sort_by_key(|a| !a.primary.unwrap_or(false));
+
+        match aliases.first() {
+            Some(alias) => (
+                alias.name.clone(),
+                Some(Locale::new(
+                    alias.locale.as_deref(),
+                    locale.script.as_deref(),
+                )),
+            ),
+            None => (credit.name.clone(), None),
+        }
+    }
+}
+
+/// language and script of a text representation.
+fn text_locale(text: &Option<inner_structs::TextRepresentation>) -> (Option<&str>, Option<&str>) {
+    match text {
+        Some(t) => (t.language.as_deref(), t.script.as_deref()),
+        None => (None, None),
+    }
+}
+
+/// sort name of a credited artist: its sort name, or the reading of a Japanese alias if
+/// the sort name is in kanji, or one generated from the credited name.
+fn credit_sort_name(credit: &inner_structs::ArtistCredit) -> String {
+    let artist = match &credit.artist {
+        Some(a) => a,
+        None => return sort_name::artist_sort(&credit.name).unwrap_or_else(|| credit.name.clone()),
+    };
+
+    let mut readings: Vec<&inner_structs::Alias> = artist
+        .aliases
+        .iter()
+        .filter(|a| a.locale.as_deref() == Some(READING_LOCALE))
+        .collect();
+    readings.sort_by_key(|a| !a.primary.unwrap_or(false));
+
+    std::iter::once(artist.sort_name.as_str())
+        .chain(readings.iter().map(|a| a.sort_name.as_str()))
+        .find(|s| !s.is_empty() && sort_name::is_readable(s))
+        .map(|s| sort_name::title_sort(s).unwrap_or_else(|| s.to_string()))
+        .unwrap_or_else(|| credit.name.clone())
 }
 
 impl FetchMeta for MusicBrainz {
@@ -69,27 +267,52 @@ impl FetchMeta for MusicBrainz {
             0,
         )?;
 
-        let release_ids = json["releases"]
-            .as_array()
-            .unwrap()
-            .into_iter()
-            .map(|e| e["id"].as_str().unwrap());
-        let mut result = Vec::with_capacity(release_ids.len());
+        let releases = json["releases"].as_array().unwrap();
+        let mut result = Vec::with_capacity(releases.len());
 
-        for release in release_ids {
-            let meta = self.fetch_all(release)?;
-            result.push(meta);
+        for release in releases {
+            let (meta, mut add_info) =
+                self.fetch_release(release["id"].as_str().unwrap(), false)?;
+            if let Some(score) = release["score"].as_i64() {
+                add_info.insert(0, ("score".into(), score.to_string()));
+            }
+            result.push((meta, add_info));
         }
 
         Ok(result)
     }
 
+    fn lookup(&self, code: &str) -> anyhow::Result<Vec<(Metadata, AddInfo)>> {
+        let code = code.trim().replace('"', "");
+        self.query(&format!("barcode:\"{0}\" OR catno:\"{0}\"", code))
+    }
+
     fn fetch_all(&self, id: &str) -> anyhow::Result<(Metadata, AddInfo)> {
+        self.fetch_release(id, true)
+    }
+}
+
+impl MusicBrainz {
+    /// release `id` with titles and artist names in the preferred locale.
+    /// query fetches many candidates, so a missing localized tracklist is reported if `warn`.
+    fn fetch_release(&self, id: &str, warn: bool) -> anyhow::Result<(Metadata, AddInfo)> {
+        let inc = match self.locale {
+            Some(_) => "recordings+genres+release-rels",
+            None => "recordings+genres",
+        };
         let release_json = self.get_mb(
             &format!("http://musicbrainz.org/ws/2/release/{}", id),
-            &[("inc", "recordings+genres")],
+            &[("inc", inc)],
             0,
         )?;
+        let release: inner_structs::Release = serde_json::from_value(release_json.clone())?;
+        let localized = self.localized_tracklist(&release, warn)?;
+        let localized_titles = localized
+            .as_ref()
+            .and_then(|t| t.media.first())
+            .map(|m| m.tracks.as_slice())
+            .unwrap_or_default();
+
         let recording_ids = release_json["media"][0]["tracks"]
             .as_array()
             .unwrap()
@@ -97,23 +320,50 @@ impl FetchMeta for MusicBrainz {
             .map(|e| e["recording"]["id"].as_str().unwrap());
 
         let mut tracks = Vec::with_capacity(recording_ids.len());
-        for recording_id in recording_ids {
+        // locale of all artist names, None if unknown or mixed
+        let mut artist_locale = None;
+        let mut first_credit = true;
+        for (i, recording_id) in recording_ids.enumerate() {
             let recording_json = self.get_mb(
                 &format!("http://musicbrainz.org/ws/2/recording/{}", recording_id),
-                &[("inc", "artists")],
+                &[("inc", "artists+aliases")],
                 0,
             )?;
             let recording: inner_structs::Recording = serde_json::from_value(recording_json)?;
 
-            let artist = recording
+            let mut artist = String::new();
+            for credit in &recording.artist_credit {
+                let (name, locale) = self.credit_name(credit);
+                if first_credit {
+                    artist_locale = locale;
+                    first_credit = false;
+                } else if artist_locale != locale {
+                    artist_locale = None;
+                }
+                artist += &name;
+                artist += &credit.joinphrase;
+            }
+
+            let artist_sort = recording
                 .artist_credit
                 .iter()
-                .fold(String::new(), |acc, e| acc + &e.name + &e.joinphrase);
+                .fold(String::new(), |acc, e| {
+                    acc + &credit_sort_name(e) + &e.joinphrase
+                });
+
+            let title = match localized_titles.get(i) {
+                Some(t) => t.title.clone(),
+                None => recording.title,
+            };
 
-            tracks.push(Track::new(recording.title, artist));
+            let mut track = Track::new(title, artist);
+            if artist_sort != track.artist {
+                track.artist_sort = Some(artist_sort);
+            }
+            track.length = recording.length.map(|ms| ((ms + 500) / 1000) as u32);
+            tracks.push(track);
         }
 
-        let release: inner_structs::Release = serde_json::from_value(release_json)?;
         let date = release.date.split("-").next().unwrap();
         let date = u32::from_str(date)?;
 
@@ -122,6 +372,9 @@ impl FetchMeta for MusicBrainz {
         if let Some(barcode) = release.barcode {
             add_info.push(("barcode".into(), barcode));
         }
+        if let Some(country) = release.country {
+            add_info.push(("country".into(), country));
+        }
         let cover_n = release.cover_art_archive.count;
         if cover_n > 0 {
             let mut cover_str = format!("count: {}, type: ", cover_n);
@@ -137,20 +390,33 @@ impl FetchMeta for MusicBrainz {
             add_info.push(("cover art".into(), cover_str))
         }
 
-        Ok((
-            Metadata::new(
-                Some(id.to_string()),
-                release.title,
-                date,
-                release
-                    .genres
-                    .first()
-                    .map(|e| e.to_string())
-                    .unwrap_or_default(),
-                tracks,
-            ),
-            add_info,
-        ))
+        let (title, text) = match localized {
+            Some(t) => (t.title, t.text_representation),
+            None => (release.title, release.text_representation),
+        };
+        let (language, script) = text_locale(&text);
+        let locale = Locale::new(language, script).to_string();
+
+        let mut meta = Metadata::new(
+            Some(id.to_string()),
+            title,
+            date,
+            release
+                .genres
+                .first()
+                .map(|e| e.to_string())
+                .unwrap_or_default(),
+            tracks,
+        );
+        if !locale.is_empty() {
+            meta.locale = Some(locale);
+        }
+        meta.artist_locale = artist_locale
+            .map(|l| l.to_string())
+            .filter(|l| !l.is_empty());
+        sort_name::fill(&mut meta);
+
+        Ok((meta, add_info))
     }
 }
 
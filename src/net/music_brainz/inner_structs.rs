@@ -8,6 +8,26 @@ pub struct CoverArtArchive {
     pub back: bool,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TextRepresentation {
+    pub language: Option<String>,
+    pub script: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct RelatedRelease {
+    pub id: String,
+    pub text_representation: Option<TextRepresentation>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Relation {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub release: Option<RelatedRelease>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "kebab-case")]
 pub struct Release {
@@ -18,18 +38,76 @@ pub struct Release {
     pub cover_art_archive: CoverArtArchive,
 
     pub barcode: Option<String>,
+    pub country: Option<String>,
+    pub text_representation: Option<TextRepresentation>,
+    #[serde(default)]
+    pub relations: Vec<Relation>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TrackTitle {
+    pub title: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Medium {
+    pub tracks: Vec<TrackTitle>,
+}
+
+/// titles of a pseudo-release, which has no date or cover art.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct Tracklist {
+    pub title: String,
+    pub text_representation: Option<TextRepresentation>,
+    pub media: Vec<Medium>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReleaseSummary {
+    pub id: String,
+    pub date: Option<String>,
+    pub status: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReleaseGroup {
+    pub releases: Vec<ReleaseSummary>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct Alias {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub sort_name: String,
+    pub locale: Option<String>,
+    pub primary: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct Artist {
+    pub name: String,
+    pub sort_name: String,
+    #[serde(default)]
+    pub aliases: Vec<Alias>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ArtistCredit {
     pub name: String,
     pub joinphrase: String,
+    pub artist: Option<Artist>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "kebab-case")]
 pub struct Recording {
     pub title: String,
+    /// length in milliseconds
+    pub length: Option<u64>,
     pub artist_credit: Vec<ArtistCredit>,
 }
 
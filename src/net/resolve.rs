@@ -0,0 +1,153 @@
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    MusicBrainz,
+    Spotify,
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Provider::MusicBrainz => write!(f, "musicbrainz"),
+            Provider::Spotify => write!(f, "spotify"),
+        }
+    }
+}
+
+impl FromStr for Provider {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Provider> {
+        match s.to_lowercase().replace(['-', '_'], "").as_str() {
+            "musicbrainz" | "mb" => Ok(Provider::MusicBrainz),
+            "spotify" => Ok(Provider::Spotify),
+            _ => anyhow::bail!("Error: unknown provider: {}", s),
+        }
+    }
+}
+
+/// kind of resource an ID points to.
+/// `Release` is a MusicBrainz release or a Spotify album.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Entity {
+    Release,
+    ReleaseGroup,
+    Track,
+}
+
+/// provider ID parsed from a bare ID, an URL or an URI.
+///
+/// accepted forms:
+///   - `https://musicbrainz.org/release/<MBID>`, `https://musicbrainz.org/release-group/<MBID>`
+///   - `https://open.spotify.com/album/<ID>?si=...`, `https://open.spotify.com/track/<ID>`
+///   - `spotify:album:<ID>`, `spotify:track:<ID>`
+///   - bare MBID (release) or bare spotify ID (album)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceId {
+    pub provider: Provider,
+    pub entity: Entity,
+    pub id: String,
+}
+
+impl ResourceId {
+    pub fn new<S: Into<String>>(provider: Provider, entity: Entity, id: S) -> ResourceId {
+        ResourceId {
+            provider,
+            entity,
+            id: id.into(),
+        }
+    }
+
+    /// parse `input` and check that it belongs to `provider`.
+    pub fn parse_for(input: &str, provider: Provider) -> anyhow::Result<ResourceId> {
+        let result = ResourceId::from_str(input)?;
+        if result.provider != provider {
+            anyhow::bail!("Error: {} is not an ID of {}", input, provider)
+        }
+        Ok(result)
+    }
+}
+
+fn is_mbid(s: &str) -> bool {
+    let groups: Vec<_> = s.split('-').collect();
+    groups.len() == 5
+        && groups
+            .iter()
+            .zip([8, 4, 4, 4, 12])
+            .all(|(g, n)| g.len() == n && g.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+fn is_spotify_id(s: &str) -> bool {
+    s.len() == 22 && s.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn parse_url(url: &str) -> anyhow::Result<ResourceId> {
+    let rest = url.split_once("://").map(|x| x.1).unwrap_or(url);
+    let rest = rest.split(['?', '#']).next().unwrap_or_default();
+
+    let mut segments = rest.split('/').filter(|s| !s.is_empty());
+    let host = segments.next().unwrap_or_default().to_lowercase();
+    let segments: Vec<_> = segments.collect();
+
+    if host == "musicbrainz.org" || host.ends_with(".musicbrainz.org") {
+        let (entity, id) = match segments.as_slice() {
+            ["release", id, ..] => (Entity::Release, *id),
+            ["release-group", id, ..] => (Entity::ReleaseGroup, *id),
+            _ => anyhow::bail!("Error: unsupported musicbrainz url: {}", url),
+        };
+        if !is_mbid(id) {
+            anyhow::bail!("Error: invalid MBID in url: {}", url)
+        }
+        Ok(ResourceId::new(Provider::MusicBrainz, entity, id))
+    } else if host == "open.spotify.com" {
+        // localized links look like /intl-ja/album/<ID>
+        let segments = match segments.first() {
+            Some(s) if s.starts_with("intl-") => &segments[1..],
+            _ => &segments[..],
+        };
+        let (entity, id) = match segments {
+            ["album", id, ..] => (Entity::Release, *id),
+            ["track", id, ..] => (Entity::Track, *id),
+            _ => anyhow::bail!("Error: unsupported spotify url: {}", url),
+        };
+        if !is_spotify_id(id) {
+            anyhow::bail!("Error: invalid spotify ID in url: {}", url)
+        }
+        Ok(ResourceId::new(Provider::Spotify, entity, id))
+    } else {
+        anyhow::bail!("Error: unsupported url: {}", url)
+    }
+}
+
+fn parse_spotify_uri(uri: &str) -> anyhow::Result<ResourceId> {
+    let (entity, id) = match uri.split(':').collect::<Vec<_>>().as_slice() {
+        ["spotify", "album", id] => (Entity::Release, *id),
+        ["spotify", "track", id] => (Entity::Track, *id),
+        _ => anyhow::bail!("Error: unsupported spotify uri: {}", uri),
+    };
+    if !is_spotify_id(id) {
+        anyhow::bail!("Error: invalid spotify ID in uri: {}", uri)
+    }
+    Ok(ResourceId::new(Provider::Spotify, entity, id))
+}
+
+impl FromStr for ResourceId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<ResourceId> {
+        let s = s.trim();
+
+        if s.contains("://") || s.contains('/') {
+            parse_url(s)
+        } else if s.starts_with("spotify:") {
+            parse_spotify_uri(s)
+        } else if is_mbid(s) {
+            Ok(ResourceId::new(Provider::MusicBrainz, Entity::Release, s))
+        } else if is_spotify_id(s) {
+            Ok(ResourceId::new(Provider::Spotify, Entity::Release, s))
+        } else {
+            anyhow::bail!("Error: could not detect provider of ID: {}", s)
+        }
+    }
+}
@@ -3,7 +3,8 @@ use std::{io::Read, path::Path, str::FromStr};
 use crate::{
     fileio::picture::Picture,
     info_struct::*,
-    net::oauth2::*,
+    net::{oauth2::*, Entity, Provider, ResourceId},
+    sort_name,
     traits::{FetchMeta, FetchPicture},
 };
 
@@ -22,6 +23,28 @@ impl Spotify {
         )?;
         Ok(Spotify { client })
     }
+
+    /// resolve `res` to a spotify album ID.
+    /// tracks are resolved to the album they belong to.
+    pub fn album_id(&self, res: &ResourceId) -> anyhow::Result<String> {
+        if res.provider != Provider::Spotify {
+            anyhow::bail!("Error: {} is not a spotify ID", res.id)
+        }
+
+        match res.entity {
+            Entity::Release => Ok(res.id.clone()),
+            Entity::Track => {
+                let resp: inner_structs::Track = self
+                    .client
+                    .get(&format!("https://api.spotify.com/v1/tracks/{}", res.id))?
+                    .query("market", "JP")
+                    .call()?
+                    .into_json()?;
+                Ok(resp.album.id)
+            }
+            Entity::ReleaseGroup => anyhow::bail!("Error: spotify has no release group"),
+        }
+    }
 }
 
 impl FetchMeta for Spotify {
@@ -54,6 +77,15 @@ impl FetchMeta for Spotify {
         }
     }
 
+    fn lookup(&self, code: &str) -> anyhow::Result<Vec<(Metadata, AddInfo)>> {
+        // spotify only knows UPC and EAN, so catalog numbers like VICL-12345 are skipped
+        let code: String = code.chars().filter(|c| !matches!(c, ' ' | '-')).collect();
+        if !matches!(code.len(), 12 | 13) || !code.chars().all(|c| c.is_ascii_digit()) {
+            return Ok(vec![]);
+        }
+        self.query(&format!("upc:{}", code))
+    }
+
     fn fetch_all(&self, id: &str) -> anyhow::Result<(Metadata, AddInfo)> {
         let mut resp: inner_structs::Album = self
             .client
@@ -86,7 +118,9 @@ impl FetchMeta for Spotify {
                 .collect::<Vec<_>>()
                 .join(", ");
 
-            tracks.push(Track::new(title, artist));
+            let mut result = Track::new(title, artist);
+            result.length = Some(((track.duration_ms + 500) / 1000) as u32);
+            tracks.push(result);
         }
 
         let ext_ids = resp
@@ -110,10 +144,10 @@ impl FetchMeta for Spotify {
             ext_ids.collect()
         };
 
-        Ok((
-            Metadata::new(Some(id.to_string()), album, date, genre, tracks),
-            add_info,
-        ))
+        let mut meta = Metadata::new(Some(id.to_string()), album, date, genre, tracks);
+        sort_name::fill(&mut meta);
+
+        Ok((meta, add_info))
     }
 }
 
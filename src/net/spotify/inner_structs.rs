@@ -16,6 +16,7 @@ pub struct Image {
 pub struct Item {
     pub track_number: i32,
     pub name: String,
+    pub duration_ms: u64,
     pub artists: Vec<Artist>,
 }
 
@@ -34,3 +35,13 @@ pub struct Album {
     pub tracks: Tracks,
     pub external_ids: serde_json::Map<String, serde_json::Value>,
 }
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AlbumRef {
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Track {
+    pub album: AlbumRef,
+}
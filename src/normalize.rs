@@ -0,0 +1,185 @@
+use std::str::FromStr;
+
+use serde::Deserialize;
+use unicode_normalization::UnicodeNormalization;
+
+/// offset from full-width forms of ASCII (U+FF01..U+FF5E) to ASCII
+const FULLWIDTH_OFFSET: u32 = 0xFEE0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Form {
+    Nfc,
+    /// also folds full-width ASCII, half-width katakana and other compatibility characters
+    Nfkc,
+}
+
+/// text normalization, applied in the order of fields.
+///
+/// from a rules file as `{"punctuation": true, "halfwidth_alnum": true, "form": "nfc"}`,
+/// or from the command line as `punct,alnum,kana,nfc`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct Normalization {
+    /// wave dashes to `〜`, curly quotes to straight ones and hyphen variants to `-`
+    #[serde(default)]
+    pub punctuation: bool,
+    /// full-width letters and digits to ASCII
+    #[serde(default)]
+    pub halfwidth_alnum: bool,
+    /// half-width katakana to full-width, joining voiced sound marks
+    #[serde(default)]
+    pub fullwidth_kana: bool,
+    pub form: Option<Form>,
+}
+
+fn is_halfwidth_kana(c: char) -> bool {
+    ('\u{FF61}'..='\u{FF9F}').contains(&c)
+}
+
+fn canonical_punctuation(c: char) -> char {
+    match c {
+        '\u{FF5E}' | '\u{223C}' | '\u{301C}' => '\u{301C}',
+        '\u{2018}' | '\u{2019}' | '\u{FF07}' => '\'',
+        '\u{201C}' | '\u{201D}' | '\u{FF02}' => '"',
+        '\u{2010}' | '\u{2011}' => '-',
+        c => c,
+    }
+}
+
+fn halfwidth_alnum(c: char) -> char {
+    match c {
+        '０'..='９' | 'Ａ'..='Ｚ' | 'ａ'..='ｚ' => {
+            char::from_u32(c as u32 - FULLWIDTH_OFFSET).unwrap_or(c)
+        }
+        c => c,
+    }
+}
+
+/// `value` with runs of half-width katakana converted to full-width.
+fn fullwidth_kana(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut run = String::new();
+
+    for c in value.chars() {
+        if is_halfwidth_kana(c) {
+            run.push(c);
+        } else {
+            result.extend(run.nfkc());
+            run.clear();
+            result.push(c);
+        }
+    }
+    result.extend(run.nfkc());
+    result
+}
+
+impl Normalization {
+    pub fn apply(&self, value: &str) -> String {
+        let mut result: String = if self.punctuation {
+            value.chars().map(canonical_punctuation).collect()
+        } else {
+            value.into()
+        };
+        if self.halfwidth_alnum {
+            result = result.chars().map(halfwidth_alnum).collect();
+        }
+        if self.fullwidth_kana {
+            result = fullwidth_kana(&result);
+        }
+        match self.form {
+            Some(Form::Nfc) => result.nfc().collect(),
+            Some(Form::Nfkc) => result.nfkc().collect(),
+            None => result,
+        }
+    }
+}
+
+impl FromStr for Normalization {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Normalization> {
+        let mut result = Normalization::default();
+        for option in s.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+            match option.to_lowercase().as_str() {
+                "nfc" => result.form = Some(Form::Nfc),
+                "nfkc" => result.form = Some(Form::Nfkc),
+                "alnum" => result.halfwidth_alnum = true,
+                "kana" => result.fullwidth_kana = true,
+                "punct" => result.punctuation = true,
+                _ => anyhow::bail!("Error: unknown normalization: {}", option),
+            }
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_halfwidth_kana() {
+        for (value, expected) in [
+            ("ｶﾞ", "ガ"),
+            ("ﾃｽﾄ", "テスト"),
+            ("ﾊﾟｰﾄ2", "パート2"),
+            ("Ａ ｶﾅ", "Ａ カナ"),
+        ] {
+            assert_eq!(fullwidth_kana(value), expected);
+        }
+    }
+
+    #[test]
+    fn converts_fullwidth_alnum() {
+        for (value, expected) in [("Ａ１", "A1"), ("ｚ９", "z9"), ("（Ａ）", "（A）")] {
+            let result: String = value.chars().map(halfwidth_alnum).collect();
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn canonicalizes_punctuation() {
+        for (value, expected) in [
+            ("～", "〜"),
+            ("∼", "〜"),
+            ("〜", "〜"),
+            ("\u{2018}a\u{2019}", "'a'"),
+            ("\u{201C}a\u{201D}", "\"a\""),
+            ("a\u{2010}b", "a-b"),
+        ] {
+            let result: String = value.chars().map(canonical_punctuation).collect();
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn nfc_composes_and_nfkc_folds() {
+        // `が` decomposed into `か` and combining voiced sound mark, and full-width `Ａ`
+        let decomposed = "\u{304B}\u{3099}Ａ";
+        let nfc = Normalization {
+            form: Some(Form::Nfc),
+            ..Default::default()
+        };
+        let nfkc = Normalization {
+            form: Some(Form::Nfkc),
+            ..Default::default()
+        };
+        assert_eq!(nfc.apply(decomposed), "がＡ");
+        assert_eq!(nfkc.apply(decomposed), "がA");
+    }
+
+    #[test]
+    fn parses_options() {
+        let result = Normalization::from_str("punct, alnum,kana,NFKC").unwrap();
+        assert_eq!(
+            result,
+            Normalization {
+                punctuation: true,
+                halfwidth_alnum: true,
+                fullwidth_kana: true,
+                form: Some(Form::Nfkc),
+            }
+        );
+        assert!(Normalization::from_str("nfd").is_err());
+    }
+}
@@ -0,0 +1,365 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Deserializer};
+
+use crate::info_struct::{Metadata, Track};
+
+/// change to apply to a single field on write.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Patch<T> {
+    /// leave the field as is
+    #[default]
+    Keep,
+    /// remove the field
+    Clear,
+    Set(T),
+}
+
+impl<T: Blank> Patch<T> {
+    /// Set if `value` is not blank, Keep otherwise.
+    pub fn from_value(value: T) -> Patch<T> {
+        if value.is_blank() {
+            Patch::Keep
+        } else {
+            Patch::Set(value)
+        }
+    }
+}
+
+impl<T: Clone + Default> Patch<T> {
+    /// value after applying patch to `current`.
+    pub fn apply(&self, current: &T) -> T {
+        match self {
+            Patch::Keep => current.clone(),
+            Patch::Clear => T::default(),
+            Patch::Set(v) => v.clone(),
+        }
+    }
+
+    /// `self`, or `fallback` if `self` is Keep.
+    pub fn or<'a>(&'a self, fallback: &'a Patch<T>) -> &'a Patch<T> {
+        match self {
+            Patch::Keep => fallback,
+            _ => self,
+        }
+    }
+}
+
+/// values which mean "not given" in a metadata document, e.g. blank fields of a template.
+pub trait Blank {
+    fn is_blank(&self) -> bool;
+}
+
+impl Blank for String {
+    fn is_blank(&self) -> bool {
+        self.trim().is_empty()
+    }
+}
+
+impl Blank for u32 {
+    fn is_blank(&self) -> bool {
+        *self == 0
+    }
+}
+
+/// absent or blank => Keep, null => Clear, value => Set
+fn patch<'de, D, T>(deserializer: D) -> Result<Patch<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Blank,
+{
+    Ok(match Option::<T>::deserialize(deserializer)? {
+        None => Patch::Clear,
+        Some(v) => Patch::from_value(v),
+    })
+}
+
+#[derive(Deserialize, Default, Debug, Clone, PartialEq, Eq)]
+pub struct TrackPatch {
+    #[serde(default, deserialize_with = "patch")]
+    pub title: Patch<String>,
+    #[serde(default, deserialize_with = "patch")]
+    pub artist: Patch<String>,
+    #[serde(default, deserialize_with = "patch")]
+    pub title_sort: Patch<String>,
+    #[serde(default, deserialize_with = "patch")]
+    pub artist_sort: Patch<String>,
+
+    /// per-track overrides of album-level fields
+    #[serde(default, deserialize_with = "patch")]
+    pub album: Patch<String>,
+    #[serde(default, deserialize_with = "patch")]
+    pub date: Patch<u32>,
+    #[serde(default, deserialize_with = "patch")]
+    pub genre: Patch<String>,
+
+    /// expected length in seconds, only used to warn about mismatching files
+    #[serde(default)]
+    pub length: Option<u32>,
+}
+
+impl From<&Track> for TrackPatch {
+    fn from(track: &Track) -> TrackPatch {
+        TrackPatch {
+            title: Patch::from_value(track.title.clone()),
+            artist: Patch::from_value(track.artist.clone()),
+            title_sort: Patch::from_value(track.title_sort.clone().unwrap_or_default()),
+            artist_sort: Patch::from_value(track.artist_sort.clone().unwrap_or_default()),
+            length: track.length,
+            ..Default::default()
+        }
+    }
+}
+
+/// field-level changes to write to audio files.
+/// a metadata document is read as a patch, where a missing or blank field means
+/// "leave as is" and an explicit null means "clear".
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MetaPatch {
+    #[serde(default, deserialize_with = "patch")]
+    pub album: Patch<String>,
+    #[serde(default, deserialize_with = "patch")]
+    pub album_sort: Patch<String>,
+    #[serde(default, deserialize_with = "patch")]
+    pub date: Patch<u32>,
+    #[serde(default, deserialize_with = "patch")]
+    pub genre: Patch<String>,
+    #[serde(default)]
+    pub tracks: Vec<TrackPatch>,
+
+    /// set track numbers from file order
+    #[serde(skip, default = "default_true")]
+    pub track_numbers: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for MetaPatch {
+    fn default() -> MetaPatch {
+        MetaPatch {
+            album: Patch::Keep,
+            album_sort: Patch::Keep,
+            date: Patch::Keep,
+            genre: Patch::Keep,
+            tracks: Vec::new(),
+            track_numbers: true,
+        }
+    }
+}
+
+impl From<&Metadata> for MetaPatch {
+    fn from(meta: &Metadata) -> MetaPatch {
+        MetaPatch {
+            album: Patch::from_value(meta.album.clone()),
+            album_sort: Patch::from_value(meta.album_sort.clone().unwrap_or_default()),
+            date: Patch::from_value(meta.date),
+            genre: Patch::from_value(meta.genre.clone()),
+            tracks: meta.tracks.iter().map(TrackPatch::from).collect(),
+            track_numbers: true,
+        }
+    }
+}
+
+/// sort name after applying `patch`, None if cleared.
+fn apply_sort(patch: &Patch<String>, current: &Option<String>) -> Option<String> {
+    match patch {
+        Patch::Keep => current.clone(),
+        Patch::Clear => None,
+        Patch::Set(v) => Some(v.clone()),
+    }
+}
+
+/// fields selectable with `--fields`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Album,
+    Date,
+    Genre,
+    Title,
+    Artist,
+    Track,
+    Cover,
+}
+
+impl FromStr for Field {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Field> {
+        match s.trim().to_lowercase().as_str() {
+            "album" => Ok(Field::Album),
+            "date" | "year" => Ok(Field::Date),
+            "genre" => Ok(Field::Genre),
+            "title" => Ok(Field::Title),
+            "artist" => Ok(Field::Artist),
+            "track" => Ok(Field::Track),
+            "cover" | "picture" => Ok(Field::Cover),
+            _ => anyhow::bail!("Error: unknown field: {}", s),
+        }
+    }
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Field::Album => "album",
+            Field::Date => "date",
+            Field::Genre => "genre",
+            Field::Title => "title",
+            Field::Artist => "artist",
+            Field::Track => "track",
+            Field::Cover => "cover",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl MetaPatch {
+    /// keep every field not in `fields`.
+    pub fn select(mut self, fields: &[Field]) -> MetaPatch {
+        let keep = |f: Field| !fields.contains(&f);
+
+        if keep(Field::Album) {
+            self.album = Patch::Keep;
+            self.album_sort = Patch::Keep;
+        }
+        if keep(Field::Date) {
+            self.date = Patch::Keep;
+        }
+        if keep(Field::Genre) {
+            self.genre = Patch::Keep;
+        }
+        if keep(Field::Track) {
+            self.track_numbers = false;
+        }
+
+        for track in &mut self.tracks {
+            if keep(Field::Title) {
+                track.title = Patch::Keep;
+                track.title_sort = Patch::Keep;
+            }
+            if keep(Field::Artist) {
+                track.artist = Patch::Keep;
+                track.artist_sort = Patch::Keep;
+            }
+            if keep(Field::Album) {
+                track.album = Patch::Keep;
+            }
+            if keep(Field::Date) {
+                track.date = Patch::Keep;
+            }
+            if keep(Field::Genre) {
+                track.genre = Patch::Keep;
+            }
+        }
+
+        self
+    }
+
+    /// metadata after applying patch to `current`, as written by `TagLib::apply`.
+    /// album-level fields are read back from the first file, so its overrides apply to them.
+    pub fn apply(&self, current: &Metadata) -> Metadata {
+        let keep = TrackPatch::default();
+        let first = self.tracks.first().unwrap_or(&keep);
+
+        let tracks = current
+            .tracks
+            .iter()
+            .enumerate()
+            .map(|(i, track)| {
+                let patch = self.tracks.get(i).unwrap_or(&keep);
+                let mut result = Track::new(
+                    patch.title.apply(&track.title),
+                    patch.artist.apply(&track.artist),
+                );
+                result.title_sort = apply_sort(&patch.title_sort, &track.title_sort);
+                result.artist_sort = apply_sort(&patch.artist_sort, &track.artist_sort);
+                result.disc = track.disc;
+                result.isrc = track.isrc.clone();
+                result.length = track.length;
+                result.audio = track.audio.clone();
+                result
+            })
+            .collect();
+
+        let mut result = Metadata::new(
+            current.id.clone(),
+            first.album.or(&self.album).apply(&current.album),
+            first.date.or(&self.date).apply(&current.date),
+            first.genre.or(&self.genre).apply(&current.genre),
+            tracks,
+        );
+        result.album_sort = apply_sort(&self.album_sort, &current.album_sort);
+        result.disc_id = current.disc_id.clone();
+        result.locale = current.locale.clone();
+        result.artist_locale = current.artist_locale.clone();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn album() -> Metadata {
+        let mut meta = Metadata::new(
+            None,
+            "Album",
+            2020,
+            "Rock",
+            vec![Track::new("One", "A"), Track::new("Two", "B")],
+        );
+        meta.album_sort = Some("Album".into());
+        meta
+    }
+
+    #[test]
+    fn picture_only_patch_keeps_track_numbers() {
+        let keep = MetaPatch {
+            track_numbers: false,
+            ..Default::default()
+        };
+        assert_eq!(MetaPatch::default().select(&[Field::Cover]), keep);
+        assert!(!keep.track_numbers);
+
+        let meta = album();
+        let result = keep.apply(&meta);
+        assert_eq!(result.album, meta.album);
+        assert_eq!(result.album_sort, meta.album_sort);
+        assert_eq!(result.date, meta.date);
+        for (new, old) in result.tracks.iter().zip(&meta.tracks) {
+            assert_eq!((&new.title, &new.artist), (&old.title, &old.artist));
+        }
+    }
+
+    #[test]
+    fn documents_set_clear_and_keep() {
+        let patch: MetaPatch = serde_json::from_str(
+            r#"{"album": null, "genre": " ", "date": 1999, "tracks": [{"title": "New"}]}"#,
+        )
+        .unwrap();
+        assert!(patch.track_numbers);
+
+        let result = patch.apply(&album());
+        assert_eq!(result.album, "");
+        assert_eq!(result.genre, "Rock");
+        assert_eq!(result.date, 1999);
+        assert_eq!(result.tracks[0].title, "New");
+        assert_eq!(result.tracks[0].artist, "A");
+        assert_eq!(result.tracks[1].title, "Two");
+    }
+
+    #[test]
+    fn album_fields_take_overrides_of_first_track() {
+        let patch = MetaPatch {
+            album: Patch::Set("Album".into()),
+            tracks: vec![TrackPatch {
+                album: Patch::Set("Disc 1".into()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(patch.apply(&album()).album, "Disc 1");
+    }
+}
@@ -0,0 +1,186 @@
+use std::io::{stdout, Stdout, Write};
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEventKind},
+    queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, ClearType},
+};
+
+use music_info::info_struct::{AddInfo, Metadata};
+
+const HELP_LIST: &str = "up/down: move, enter: select, d: details, q: quit";
+const HELP_DETAIL: &str = "up/down: scroll, enter: select, d/esc: back, q: quit";
+
+/// restores terminal when dropped, even on error.
+struct Screen {
+    out: Stdout,
+}
+
+impl Screen {
+    fn new() -> anyhow::Result<Screen> {
+        let mut out = stdout();
+        terminal::enable_raw_mode()?;
+        crossterm::execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(Screen { out })
+    }
+
+    fn draw(&mut self, lines: &[String], selected: Option<usize>) -> anyhow::Result<()> {
+        let (width, _) = terminal::size()?;
+        queue!(self.out, terminal::Clear(ClearType::All))?;
+
+        for (row, line) in lines.iter().enumerate() {
+            let line: String = line.chars().take(width as usize).collect();
+            queue!(self.out, cursor::MoveTo(0, row as u16))?;
+            if Some(row) == selected {
+                queue!(
+                    self.out,
+                    SetAttribute(Attribute::Reverse),
+                    Print(line),
+                    SetAttribute(Attribute::Reset)
+                )?;
+            } else {
+                queue!(self.out, Print(line))?;
+            }
+        }
+
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = crossterm::execute!(self.out, cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn add_info_value<'a>(add_info: &'a AddInfo, key: &str) -> Option<&'a str> {
+    add_info
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.as_str())
+}
+
+/// one line summary of a candidate.
+pub fn summarize(meta: &Metadata, add_info: &AddInfo) -> String {
+    let mut result = format!(
+        "{} / {} ({}), {} tracks",
+        meta.album,
+        meta.album_artist(),
+        meta.date,
+        meta.tracks.len()
+    );
+
+    if let Some(provider) = add_info_value(add_info, "provider") {
+        result = format!("[{}] {}", provider, result);
+    }
+    if let Some(country) = add_info_value(add_info, "country") {
+        result += &format!(", {}", country);
+    }
+    if let Some(distance) = add_info_value(add_info, "match distance") {
+        // only overall distance, details are in the detail view
+        let distance = distance.split(' ').next().unwrap_or_default();
+        result += &format!(", distance {}", distance);
+    } else if let Some(score) = add_info_value(add_info, "score") {
+        result += &format!(", score {}", score);
+    }
+
+    result
+}
+
+fn details(meta: &Metadata, add_info: &AddInfo) -> Vec<String> {
+    let mut result: Vec<String> = meta.to_string().lines().map(String::from).collect();
+    if !add_info.is_empty() {
+        result.push("additional info:".into());
+    }
+    for (k, v) in add_info {
+        result.push(format!("  {}: {}", k, v));
+    }
+    result
+}
+
+/// let user pick one of `candidates` in terminal, returns None if user quit.
+pub fn pick(candidates: &[(Metadata, AddInfo)]) -> anyhow::Result<Option<usize>> {
+    if candidates.is_empty() {
+        return Ok(None);
+    }
+
+    let summaries: Vec<_> = candidates
+        .iter()
+        .map(|(meta, add_info)| summarize(meta, add_info))
+        .collect();
+
+    let mut screen = Screen::new()?;
+    let mut selected = 0;
+    // first line of list or detail view on screen
+    let mut top = 0;
+    let mut detail: Option<Vec<String>> = None;
+
+    loop {
+        let (_, height) = terminal::size()?;
+        let rows = (height as usize).saturating_sub(2).max(1);
+
+        let mut lines = Vec::with_capacity(rows + 2);
+        let highlight = match &detail {
+            Some(detail) => {
+                lines.push(format!("{} | {}", summaries[selected], HELP_DETAIL));
+                lines.push(String::new());
+                lines.extend(detail.iter().skip(top).take(rows).cloned());
+                None
+            }
+            None => {
+                if selected < top {
+                    top = selected;
+                } else if selected >= top + rows {
+                    top = selected + 1 - rows;
+                }
+                lines.push(format!("{} candidates | {}", candidates.len(), HELP_LIST));
+                lines.push(String::new());
+                lines.extend(
+                    summaries
+                        .iter()
+                        .enumerate()
+                        .skip(top)
+                        .take(rows)
+                        .map(|(i, s)| format!("{:>3}) {}", i + 1, s)),
+                );
+                Some(selected - top + 2)
+            }
+        };
+        screen.draw(&lines, highlight)?;
+
+        let key = match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+
+        match (&detail, key.code) {
+            (_, KeyCode::Char('q')) => return Ok(None),
+            (_, KeyCode::Enter) => return Ok(Some(selected)),
+            (None, KeyCode::Esc) => return Ok(None),
+            (None, KeyCode::Up | KeyCode::Char('k')) => selected = selected.saturating_sub(1),
+            (None, KeyCode::Down | KeyCode::Char('j')) => {
+                selected = (selected + 1).min(candidates.len() - 1)
+            }
+            (None, KeyCode::PageUp) => selected = selected.saturating_sub(rows),
+            (None, KeyCode::PageDown) => selected = (selected + rows).min(candidates.len() - 1),
+            (None, KeyCode::Char('d') | KeyCode::Right) => {
+                let (meta, add_info) = &candidates[selected];
+                detail = Some(details(meta, add_info));
+                top = 0;
+            }
+            (Some(_), KeyCode::Char('d') | KeyCode::Esc | KeyCode::Left) => {
+                detail = None;
+                top = 0;
+            }
+            (Some(_), KeyCode::Up | KeyCode::Char('k')) => top = top.saturating_sub(1),
+            (Some(d), KeyCode::Down | KeyCode::Char('j')) => {
+                top = (top + 1).min(d.len().saturating_sub(1))
+            }
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,469 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{info_struct::Metadata, scan::is_audio};
+
+/// extensions of files moved with an album, e.g. covers and rip logs.
+pub const EXTRA_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "png", "gif", "webp", "bmp", "log", "cue", "txt", "nfo", "m3u", "m3u8", "pdf",
+];
+
+const FIELDS: &[&str] = &[
+    "album",
+    "albumartist",
+    "artist",
+    "title",
+    "genre",
+    "date",
+    "date.year",
+    "track",
+    "tracks",
+    "disc",
+];
+
+/// names not allowed as file name on Windows, with or without extension.
+const RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// rules of characters allowed in file names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filesystem {
+    /// only `/` and NUL are replaced
+    Posix,
+    /// also `<>:"\|?*`, control characters, trailing dots and spaces and reserved names
+    Windows,
+}
+
+impl FromStr for Filesystem {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Filesystem> {
+        match s.to_lowercase().as_str() {
+            "posix" | "unix" => Ok(Filesystem::Posix),
+            "windows" | "portable" => Ok(Filesystem::Windows),
+            _ => anyhow::bail!("Error: unknown filesystem: {}", s),
+        }
+    }
+}
+
+impl Filesystem {
+    /// make `name` usable as a single path component.
+    pub fn sanitize(&self, name: &str) -> String {
+        let mut result: String = name
+            .chars()
+            .map(|c| match (self, c) {
+                (_, '/' | '\0') => '_',
+                (Filesystem::Windows, '<' | '>' | ':' | '"' | '\\' | '|' | '?' | '*') => '_',
+                (Filesystem::Windows, c) if c.is_control() => '_',
+                (_, c) => c,
+            })
+            .collect();
+
+        if *self == Filesystem::Windows {
+            result = result.trim_end_matches(['.', ' ']).to_string();
+
+            let stem = result.split('.').next().unwrap_or_default();
+            if RESERVED.contains(&stem.to_uppercase().as_str()) {
+                result.insert(stem.len(), '_');
+            }
+        }
+        result.trim().to_string()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Segment {
+    Literal(String),
+    /// field name, and minimum width of numbers padded with zeros
+    Field(String, usize),
+}
+
+/// path template like `{albumartist}/{date.year} - {album}/{disc}-{track:02} {title}`.
+///
+/// `/` separates directories, `{{` and `}}` are literal braces.
+/// fields: album, albumartist, artist, title, genre, date, date.year, track, tracks, disc.
+/// disc is read from FLAC, MP3 and Ogg files, and is 1 if unknown.
+/// extension of the audio file is appended to the rendered path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl FromStr for Template {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Template> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut field = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => field.push(c),
+                            None => anyhow::bail!("Error: unclosed `{{` in template: {}", s),
+                        }
+                    }
+
+                    let (name, width) = match field.split_once(':') {
+                        Some((name, width)) => (
+                            name.trim(),
+                            usize::from_str(width.trim()).map_err(|_| {
+                                anyhow::anyhow!("Error: invalid width in template: {{{}}}", field)
+                            })?,
+                        ),
+                        None => (field.trim(), 0),
+                    };
+                    if !FIELDS.contains(&name) {
+                        anyhow::bail!("Error: unknown field in template: {{{}}}", field)
+                    }
+
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Field(name.into(), width));
+                }
+                '}' => anyhow::bail!("Error: unmatched `}}` in template: {}", s),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Template { segments })
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(s) => write!(f, "{}", s.replace('{', "{{").replace('}', "}}"))?,
+                Segment::Field(name, 0) => write!(f, "{{{}}}", name)?,
+                Segment::Field(name, width) => write!(f, "{{{}:{:02}}}", name, width)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Template {
+    pub(crate) fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// relative path of track `index` (0-origin) of `meta`, without extension.
+    pub fn render(&self, meta: &Metadata, index: usize, fs: Filesystem) -> anyhow::Result<PathBuf> {
+        let track = meta.tracks.get(index).ok_or(anyhow::anyhow!(
+            "Error: no metadata for track {}",
+            index + 1
+        ))?;
+        let number = |n: u32, width: usize| format!("{:0width$}", n, width = width);
+
+        let mut path = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(s) => path += s,
+                Segment::Field(name, width) => {
+                    let value = match name.as_str() {
+                        "album" => meta.album.clone(),
+                        "albumartist" => meta.album_artist(),
+                        "artist" => track.artist.clone(),
+                        "title" => track.title.clone(),
+                        "genre" => meta.genre.clone(),
+                        "date" | "date.year" => number(meta.date, *width),
+                        "track" => number(index as u32 + 1, *width),
+                        "tracks" => number(meta.tracks.len() as u32, *width),
+                        "disc" => number(track.disc.unwrap_or(1), *width),
+                        _ => unreachable!(),
+                    };
+                    // field values never create directories
+                    path += &value.replace('/', "_");
+                }
+            }
+        }
+
+        let result: PathBuf = path
+            .split('/')
+            .map(|c| fs.sanitize(c))
+            .filter(|c| !c.is_empty())
+            .collect();
+        if result.as_os_str().is_empty() {
+            anyhow::bail!(
+                "Error: template rendered to empty path for track {}",
+                index + 1
+            )
+        }
+        Ok(result)
+    }
+}
+
+/// one file to move.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Move {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} -> {}", self.from.display(), self.to.display())
+    }
+}
+
+fn extension_of(path: &Path) -> String {
+    path.extension()
+        .map(|e| e.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn with_extension(path: PathBuf, ext: &str) -> PathBuf {
+    // set_extension would cut titles containing dots
+    if ext.is_empty() {
+        path
+    } else {
+        let mut s = path.into_os_string();
+        s.push(".");
+        s.push(ext);
+        s.into()
+    }
+}
+
+/// moves of an album.
+pub struct Plan {
+    moves: Vec<Move>,
+    taken: HashSet<PathBuf>,
+}
+
+impl Plan {
+    fn new() -> Plan {
+        Plan {
+            moves: Vec::new(),
+            taken: HashSet::new(),
+        }
+    }
+
+    /// add a move to `stem` + `ext`, numbering it like `name (2).flac` if the target is taken.
+    fn add(&mut self, from: &Path, stem: PathBuf, ext: &str) -> PathBuf {
+        let mut to = with_extension(stem.clone(), ext);
+        let mut n = 2;
+        while self.taken.contains(&to) || (to.exists() && !same_file(from, &to)) {
+            let mut numbered = stem.clone().into_os_string();
+            numbered.push(format!(" ({})", n));
+            to = with_extension(numbered.into(), ext);
+            n += 1;
+        }
+
+        self.taken.insert(to.clone());
+        if from != to {
+            self.moves.push(Move {
+                from: from.into(),
+                to: to.clone(),
+            });
+        }
+        to
+    }
+
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    /// move files, creating directories as needed and removing source directories left empty.
+    pub fn execute(&self) -> anyhow::Result<()> {
+        let mut sources = Vec::new();
+
+        for m in &self.moves {
+            if m.to.exists() && !same_file(&m.from, &m.to) {
+                anyhow::bail!("Error: {} already exists", m.to.display())
+            }
+            if let Some(parent) = m.to.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+
+            // rename fails across filesystems, so copy and remove then
+            if std::fs::rename(&m.from, &m.to).is_err() {
+                std::fs::copy(&m.from, &m.to)?;
+                std::fs::remove_file(&m.from)?;
+            }
+
+            if let Some(parent) = m.from.parent() {
+                if !sources.contains(&parent) {
+                    sources.push(parent);
+                }
+            }
+        }
+
+        for dir in sources {
+            // fails if not empty, which is fine
+            let _ = std::fs::remove_dir(dir);
+        }
+        Ok(())
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// plan moves of `files` to paths rendered from `template` under `dest`.
+/// `files` are indexed by track, like inputs of `TagLib::new`.
+///
+/// files sharing the stem of an audio file (e.g. lyrics) are renamed along with it,
+/// and covers and other extra files are moved to the directory of the first track
+/// if their directory holds no audio files of other albums.
+pub fn plan<P: AsRef<Path>>(
+    meta: &Metadata,
+    files: &[Option<P>],
+    template: &Template,
+    dest: &Path,
+    fs: Filesystem,
+) -> anyhow::Result<Plan> {
+    let mut plan = Plan::new();
+    let mut claimed: HashSet<PathBuf> = HashSet::new();
+    let mut album_dir = None;
+    let mut source_dirs: Vec<PathBuf> = Vec::new();
+
+    for (i, file) in files.iter().enumerate() {
+        let file = match file {
+            Some(f) => f.as_ref(),
+            None => continue,
+        };
+        claimed.insert(file.into());
+
+        let ext = extension_of(file);
+        let to = plan.add(file, dest.join(template.render(meta, i, fs)?), &ext);
+        let new_stem = if ext.is_empty() {
+            to.clone()
+        } else {
+            to.with_extension("")
+        };
+        if album_dir.is_none() {
+            album_dir = to.parent().map(Path::to_path_buf);
+        }
+
+        let dir = file.parent().unwrap_or(Path::new("."));
+        if !source_dirs.iter().any(|d| d == dir) {
+            source_dirs.push(dir.into());
+        }
+
+        // sidecars: same stem, different extension
+        let stem = file.file_stem().unwrap_or_default();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path != file && path.is_file() && path.file_stem() == Some(stem) && !is_audio(&path)
+            {
+                claimed.insert(path.clone());
+                plan.add(&path, new_stem.clone(), &extension_of(&path));
+            }
+        }
+    }
+
+    let album_dir = match album_dir {
+        Some(dir) => dir,
+        None => return Ok(plan),
+    };
+
+    for dir in source_dirs {
+        let entries: Vec<_> = std::fs::read_dir(&dir)?
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.is_file() && !claimed.contains(p))
+            .collect();
+
+        if entries.iter().any(is_audio) {
+            println!(
+                "Warning: {} has audio files of other albums, extra files are not moved",
+                dir.display()
+            );
+            continue;
+        }
+
+        for path in entries {
+            let ext = extension_of(&path);
+            if EXTRA_EXTENSIONS.contains(&ext.to_lowercase().as_str()) {
+                let stem = album_dir.join(path.file_stem().unwrap_or_default());
+                plan.add(&path, stem, &ext);
+            }
+        }
+    }
+
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::info_struct::Track;
+
+    #[test]
+    fn template_round_trips() {
+        for text in [
+            "{albumartist}/{date.year} - {album}/{disc}-{track:02} {title}",
+            "{{literal}} {title}",
+        ] {
+            let template: Template = text.parse().unwrap();
+            assert_eq!(template.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn template_parses_fields_and_escapes() {
+        let template: Template = "{{{track:3}}}".parse().unwrap();
+        assert_eq!(
+            template.segments(),
+            &[
+                Segment::Literal("{".into()),
+                Segment::Field("track".into(), 3),
+                Segment::Literal("}".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_templates_are_rejected() {
+        for text in ["{nope}", "{title", "title}", "{track:x}"] {
+            assert!(text.parse::<Template>().is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn render_pads_numbers_and_sanitizes_values() {
+        let mut second = Track::new("Why?/Not", "Artist");
+        second.disc = Some(2);
+        let meta = Metadata::new(
+            None,
+            "Album: Live",
+            2020,
+            "",
+            vec![Track::new("One", "Artist"), second],
+        );
+        let template: Template = "{album}/{disc}-{track:02} {title}".parse().unwrap();
+
+        let first = template.render(&meta, 0, Filesystem::Posix).unwrap();
+        assert_eq!(first, PathBuf::from("Album: Live/1-01 One"));
+        let second = template.render(&meta, 1, Filesystem::Windows).unwrap();
+        assert_eq!(second, PathBuf::from("Album_ Live/2-02 Why__Not"));
+        assert!(template.render(&meta, 2, Filesystem::Posix).is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    cmp::Ordering,
+    collections::HashSet,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::fileio::{self, RawTags};
+
+/// extensions of audio files taglib can handle.
+pub const AUDIO_EXTENSIONS: &[&str] = &[
+    "flac", "mp3", "m4a", "mp4", "aac", "ogg", "oga", "opus", "wav", "aif", "aiff", "ape", "wv",
+    "wma",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    /// by file name, numbers compared by value: `2.flac` < `10.flac`
+    Natural,
+    /// by existing disc and track number tags, then by file name
+    Tags,
+}
+
+impl FromStr for Order {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Order> {
+        match s.to_lowercase().as_str() {
+            "natural" | "name" => Ok(Order::Natural),
+            "tags" | "track" => Ok(Order::Tags),
+            _ => anyhow::bail!("Error: unknown order: {}", s),
+        }
+    }
+}
+
+/// audio files in one directory, treated as an album.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Album {
+    pub dir: PathBuf,
+    pub files: Vec<PathBuf>,
+}
+
+pub fn is_audio<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref()
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| AUDIO_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// compare strings treating runs of digits as numbers, ignoring case.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let take_number = |it: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut digits = String::new();
+                    while let Some(c) = it.peek().filter(|c| c.is_ascii_digit()) {
+                        digits.push(*c);
+                        it.next();
+                    }
+                    digits.trim_start_matches('0').to_string()
+                };
+                let x = take_number(&mut a);
+                let y = take_number(&mut b);
+                let ord = x.len().cmp(&y.len()).then_with(|| x.cmp(&y));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ord = x.to_lowercase().cmp(y.to_lowercase());
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+pub fn sort_files(files: &mut [PathBuf], order: Order) {
+    match order {
+        Order::Natural => files.sort_by(|a, b| natural_cmp(&file_name(a), &file_name(b))),
+        Order::Tags => sort_by_numbers(files, |f| {
+            let track = RawTags::read(f).map(|t| t.track).unwrap_or(0);
+            (fileio::read_disc(f), track)
+        }),
+    }
+}
+
+/// sort `files` by (disc, track) of `numbers`, then by file name.
+/// files without track number (0) go last in their disc, files without disc number
+/// count as disc 1.
+fn sort_by_numbers<F: Fn(&Path) -> (Option<u32>, u32)>(files: &mut [PathBuf], numbers: F) {
+    let mut keyed: Vec<_> = files
+        .iter()
+        .map(|f| {
+            let (disc, track) = numbers(f);
+            let disc = disc.filter(|d| *d != 0).unwrap_or(1);
+            let track = if track == 0 { u32::MAX } else { track };
+            ((disc, track), f.clone())
+        })
+        .collect();
+    keyed.sort_by(|(ta, a), (tb, b)| {
+        ta.cmp(tb)
+            .then_with(|| natural_cmp(&file_name(a), &file_name(b)))
+    });
+    for (dst, (_, src)) in files.iter_mut().zip(keyed) {
+        *dst = src;
+    }
+}
+
+/// find albums under `dir`.
+/// without `recursive`, `dir` itself is the only album.
+/// with `recursive`, every directory containing audio files is an album,
+/// also if it has subdirectories. symlinked directories are followed once,
+/// so links back to a parent do not loop.
+pub fn scan_dir<P: AsRef<Path>>(
+    dir: P,
+    recursive: bool,
+    order: Order,
+) -> anyhow::Result<Vec<Album>> {
+    let mut result = Vec::new();
+    let mut pending = vec![dir.as_ref().to_path_buf()];
+    let mut visited = HashSet::new();
+
+    while let Some(dir) = pending.pop() {
+        if !visited.insert(std::fs::canonicalize(&dir)?) {
+            continue;
+        }
+
+        let mut files = Vec::new();
+        let mut subdirs = Vec::new();
+
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                subdirs.push(path);
+            } else if is_audio(&path) {
+                files.push(path);
+            }
+        }
+
+        if !files.is_empty() {
+            sort_files(&mut files, order);
+            result.push(Album {
+                dir: dir.clone(),
+                files,
+            });
+        }
+        if recursive {
+            pending.extend(subdirs);
+        }
+    }
+
+    result.sort_by(|a, b| natural_cmp(&a.dir.to_string_lossy(), &b.dir.to_string_lossy()));
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn natural_order_compares_numbers_by_value() {
+        assert_eq!(natural_cmp("Track2", "Track10"), Ordering::Less);
+        assert_eq!(natural_cmp("Track10", "Track2"), Ordering::Greater);
+        assert_eq!(natural_cmp("track02", "Track2"), Ordering::Equal);
+        assert_eq!(natural_cmp("a", "a1"), Ordering::Less);
+        assert_eq!(natural_cmp("Disc 1/09", "Disc 1/10"), Ordering::Less);
+    }
+
+    #[test]
+    fn sorts_by_disc_then_track() {
+        let numbers = |path: &Path| match path.to_str().unwrap() {
+            "a.flac" => (Some(2), 1),
+            "b.flac" => (Some(1), 2),
+            "c.flac" => (None, 1),
+            "d.flac" => (Some(1), 0),
+            _ => (Some(2), 1),
+        };
+        let mut files: Vec<PathBuf> = ["e.flac", "d.flac", "a.flac", "b.flac", "c.flac"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+
+        sort_by_numbers(&mut files, numbers);
+        let names: Vec<_> = files.iter().map(|f| f.to_str().unwrap()).collect();
+        assert_eq!(
+            names,
+            vec!["c.flac", "b.flac", "d.flac", "a.flac", "e.flac"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_loops_are_scanned_once() {
+        let root = std::env::temp_dir().join(format!("music_info_{}_scan", std::process::id()));
+        let album = root.join("album");
+        std::fs::create_dir_all(&album).unwrap();
+        std::fs::write(album.join("01.flac"), b"").unwrap();
+        std::os::unix::fs::symlink(&root, album.join("loop")).unwrap();
+
+        let result = scan_dir(&root, true, Order::Natural);
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let result = result.unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].files, vec![album.join("01.flac")]);
+    }
+}
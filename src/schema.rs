@@ -0,0 +1,151 @@
+use std::path::Path;
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::info_struct::Metadata;
+
+/// schema version of metadata documents written by this version.
+/// bump it and add a migration to `MIGRATIONS` when the document format changes.
+pub const VERSION: u64 = 1;
+
+/// key of the schema version in documents. documents without it are version 0.
+pub const VERSION_KEY: &str = "schema_version";
+
+/// migration from version `i` to `i + 1` at index `i`
+const MIGRATIONS: [fn(&mut Value); VERSION as usize] = [migrate_v0];
+
+/// unversioned documents have the same layout as version 1.
+/// documents written before `id` was saved lack it, which is fine as it is optional.
+fn migrate_v0(_: &mut Value) {}
+
+/// `value` serialized with the current schema version as its first field.
+#[derive(Serialize)]
+pub struct Versioned<'a, T> {
+    schema_version: u64,
+    #[serde(flatten)]
+    inner: &'a T,
+}
+
+impl<'a, T> Versioned<'a, T> {
+    pub fn new(inner: &'a T) -> Versioned<'a, T> {
+        Versioned {
+            schema_version: VERSION,
+            inner,
+        }
+    }
+}
+
+/// JSON Schema of metadata documents, generated from `Metadata`.
+pub fn json_schema() -> Value {
+    let mut schema = serde_json::to_value(schemars::schema_for!(Metadata)).unwrap();
+    schema["title"] = "music_info metadata".into();
+    schema["properties"][VERSION_KEY] = serde_json::json!({
+        "description": "schema version of the document, unversioned documents are migrated on read",
+        "type": "integer",
+        "minimum": 0,
+        "maximum": VERSION,
+    });
+    schema
+}
+
+/// migrate `value` to the current version, removing the version key.
+/// returns the version of the document.
+fn migrate(value: &mut Value) -> anyhow::Result<u64> {
+    let version = match value.as_object_mut().map(|o| o.remove(VERSION_KEY)) {
+        None => anyhow::bail!("$: expected an object"),
+        Some(None) => 0,
+        Some(Some(v)) => v.as_u64().ok_or(anyhow::anyhow!(
+            "$.{}: expected a non-negative integer, found {}",
+            VERSION_KEY,
+            v
+        ))?,
+    };
+
+    if version > VERSION {
+        anyhow::bail!(
+            "schema version {} is newer than supported version {}, update music_info to read it",
+            version,
+            VERSION
+        )
+    }
+
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(value);
+    }
+    Ok(version)
+}
+
+/// `message` of serde_json error without its position.
+fn message(err: &serde_json::Error) -> String {
+    let text = err.to_string();
+    let position = format!(" at line {} column {}", err.line(), err.column());
+    text.strip_suffix(&position).unwrap_or(&text).to_string()
+}
+
+/// JSON path of `path` reported by serde_path_to_error.
+fn json_path(path: &serde_path_to_error::Path) -> String {
+    match path.to_string().as_str() {
+        "." => "$".into(),
+        p => format!("$.{}", p),
+    }
+}
+
+/// parse a versioned JSON document of `source`, migrating older versions.
+/// errors name the JSON path and line of the offending value.
+pub fn from_json<T: DeserializeOwned>(text: &str, source: &Path) -> anyhow::Result<T> {
+    let fail = |msg: String| anyhow::anyhow!("Error: {}: {}", source.display(), msg);
+
+    let mut value: Value = serde_json::from_str(text).map_err(|e| {
+        fail(format!(
+            "invalid JSON at line {} column {}: {}",
+            e.line(),
+            e.column(),
+            message(&e)
+        ))
+    })?;
+    let original = value.clone();
+    migrate(&mut value).map_err(|e| fail(e.to_string()))?;
+
+    let unchanged = match (&original, &value) {
+        (Value::Object(a), Value::Object(b)) => {
+            a.iter().filter(|(k, _)| *k != VERSION_KEY).eq(b.iter())
+        }
+        _ => false,
+    };
+
+    if unchanged {
+        // deserialize the text itself, so errors have line numbers
+        let de = &mut serde_json::Deserializer::from_str(text);
+        serde_path_to_error::deserialize(de).map_err(|e| {
+            let inner = e.inner();
+            fail(format!(
+                "{}: {} (line {}, column {})",
+                json_path(e.path()),
+                message(inner),
+                inner.line(),
+                inner.column()
+            ))
+        })
+    } else {
+        deserialize(value, source)
+    }
+}
+
+/// deserialize a versioned document of `source` in another format, parsed to `value`,
+/// migrating older versions.
+pub fn from_value<T: DeserializeOwned>(mut value: Value, source: &Path) -> anyhow::Result<T> {
+    migrate(&mut value).map_err(|e| anyhow::anyhow!("Error: {}: {}", source.display(), e))?;
+    deserialize(value, source)
+}
+
+fn deserialize<T: DeserializeOwned>(value: Value, source: &Path) -> anyhow::Result<T> {
+    serde_path_to_error::deserialize(value).map_err(|e| {
+        anyhow::anyhow!(
+            "Error: {}: {}: {}",
+            source.display(),
+            json_path(e.path()),
+            e.inner()
+        )
+    })
+}
@@ -0,0 +1,59 @@
+use std::path::Path;
+
+use crate::{info_struct::Metadata, net::Provider};
+
+/// album and artist used to search providers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchTerms {
+    pub album: String,
+    pub artist: String,
+}
+
+impl SearchTerms {
+    pub fn new<S: Into<String>>(album: S, artist: S) -> SearchTerms {
+        SearchTerms {
+            album: album.into(),
+            artist: artist.into(),
+        }
+    }
+
+    /// terms from existing tags, None if album is not tagged.
+    pub fn from_metadata(meta: &Metadata) -> Option<SearchTerms> {
+        if meta.album.trim().is_empty() {
+            None
+        } else {
+            Some(SearchTerms::new(
+                meta.album.trim().to_string(),
+                meta.album_artist(),
+            ))
+        }
+    }
+
+    /// terms from directory name, split to artist and album on ` - ` if present.
+    pub fn from_dir_name<P: AsRef<Path>>(dir: P) -> Option<SearchTerms> {
+        let name = dir.as_ref().file_name()?.to_str()?.trim();
+        if name.is_empty() {
+            return None;
+        }
+
+        match name.split_once(" - ") {
+            Some((artist, album)) => Some(SearchTerms::new(album.trim(), artist.trim())),
+            None => Some(SearchTerms::new(name, "")),
+        }
+    }
+
+    /// query string in the syntax of `provider`.
+    pub fn to_query(&self, provider: Provider) -> String {
+        let album = self.album.replace('"', "");
+        let artist = self.artist.replace('"', "");
+
+        match provider {
+            Provider::MusicBrainz if artist.is_empty() => format!("release:\"{}\"", album),
+            Provider::MusicBrainz => {
+                format!("release:\"{}\" AND artist:\"{}\"", album, artist)
+            }
+            Provider::Spotify if artist.is_empty() => format!("album:{}", album),
+            Provider::Spotify => format!("album:{} artist:{}", album, artist),
+        }
+    }
+}
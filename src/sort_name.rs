@@ -0,0 +1,259 @@
+use unicode_normalization::UnicodeNormalization;
+
+use crate::info_struct::Metadata;
+
+/// offset from katakana (U+30A1..U+30F6) to hiragana
+const KATAKANA_OFFSET: u32 = 0x60;
+
+/// Hepburn romanization of hiragana and of katakana without hiragana counterparts
+#[rustfmt::skip]
+const ROMAJI: &[(char, &str)] = &[
+    ('あ', "a"), ('い', "i"), ('う', "u"), ('え', "e"), ('お', "o"),
+    ('か', "ka"), ('き', "ki"), ('く', "ku"), ('け', "ke"), ('こ', "ko"),
+    ('が', "ga"), ('ぎ', "gi"), ('ぐ', "gu"), ('げ', "ge"), ('ご', "go"),
+    ('さ', "sa"), ('し', "shi"), ('す', "su"), ('せ', "se"), ('そ', "so"),
+    ('ざ', "za"), ('じ', "ji"), ('ず', "zu"), ('ぜ', "ze"), ('ぞ', "zo"),
+    ('た', "ta"), ('ち', "chi"), ('つ', "tsu"), ('て', "te"), ('と', "to"),
+    ('だ', "da"), ('ぢ', "ji"), ('づ', "zu"), ('で', "de"), ('ど', "do"),
+    ('な', "na"), ('に', "ni"), ('ぬ', "nu"), ('ね', "ne"), ('の', "no"),
+    ('は', "ha"), ('ひ', "hi"), ('ふ', "fu"), ('へ', "he"), ('ほ', "ho"),
+    ('ば', "ba"), ('び', "bi"), ('ぶ', "bu"), ('べ', "be"), ('ぼ', "bo"),
+    ('ぱ', "pa"), ('ぴ', "pi"), ('ぷ', "pu"), ('ぺ', "pe"), ('ぽ', "po"),
+    ('ま', "ma"), ('み', "mi"), ('む', "mu"), ('め', "me"), ('も', "mo"),
+    ('や', "ya"), ('ゆ', "yu"), ('よ', "yo"),
+    ('ら', "ra"), ('り', "ri"), ('る', "ru"), ('れ', "re"), ('ろ', "ro"),
+    ('わ', "wa"), ('ゐ', "i"), ('ゑ', "e"), ('を', "o"), ('ん', "n"), ('ゔ', "vu"),
+    ('ぁ', "a"), ('ぃ', "i"), ('ぅ', "u"), ('ぇ', "e"), ('ぉ', "o"),
+    ('ゃ', "ya"), ('ゅ', "yu"), ('ょ', "yo"), ('ゎ', "wa"), ('ゕ', "ka"), ('ゖ', "ke"),
+    ('ヷ', "va"), ('ヸ', "vi"), ('ヹ', "ve"), ('ヺ', "vo"),
+];
+
+/// articles moved to the end of artist sort names, as in `Beatles, The`
+const ARTICLES: [&str; 1] = ["The"];
+
+fn is_kana(c: char) -> bool {
+    ('\u{3041}'..='\u{30FA}').contains(&c) || c == 'ー'
+}
+
+fn is_kanji(c: char) -> bool {
+    ('\u{4E00}'..='\u{9FFF}').contains(&c) || ('\u{3400}'..='\u{4DBF}').contains(&c) || c == '々'
+}
+
+fn to_hiragana(c: char) -> char {
+    match c {
+        '\u{30A1}'..='\u{30F6}' => char::from_u32(c as u32 - KATAKANA_OFFSET).unwrap_or(c),
+        c => c,
+    }
+}
+
+fn romaji(c: char) -> Option<&'static str> {
+    ROMAJI.iter().find(|(k, _)| *k == c).map(|(_, r)| *r)
+}
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'i' | 'u' | 'e' | 'o')
+}
+
+/// `base` joined with the small kana after it, like `ki` and `ゃ` to `kya` or `fu` and `ぁ` to `fa`.
+fn combine(base: &str, small: char) -> Option<String> {
+    match small {
+        'ゃ' | 'ゅ' | 'ょ' => {
+            let stem = base.strip_suffix('i').filter(|s| !s.is_empty())?;
+            let y = romaji(small)?;
+            match stem {
+                "sh" | "ch" | "j" => Some(format!("{}{}", stem, &y[1..])),
+                _ => Some(format!("{}{}", stem, y)),
+            }
+        }
+        'ぁ' | 'ぃ' | 'ぅ' | 'ぇ' | 'ぉ' => {
+            let stem = match base {
+                "u" => "w",
+                _ => base.trim_end_matches(is_vowel),
+            };
+            let vowel = romaji(small)?;
+            (!stem.is_empty()).then(|| format!("{}{}", stem, vowel))
+        }
+        _ => None,
+    }
+}
+
+fn punctuation(c: char) -> Option<&'static str> {
+    match c {
+        '、' => Some(", "),
+        '。' => Some(". "),
+        '・' => Some(" "),
+        '「' | '」' | '『' | '』' => Some("\""),
+        '〜' => Some("~"),
+        _ => None,
+    }
+}
+
+/// syllables of a run of kana, with small kana combined where possible.
+fn syllables(run: &[char]) -> Vec<String> {
+    let mut result: Vec<String> = Vec::new();
+    for &c in run {
+        let combined = result
+            .last()
+            .filter(|last| last.is_ascii())
+            .and_then(|last| combine(last, c));
+        match (combined, romaji(c)) {
+            (Some(s), _) => *result.last_mut().unwrap() = s,
+            (None, Some(r)) => result.push(r.into()),
+            // `っ` and `ー` are resolved by their neighbours
+            (None, None) => result.push(c.to_string()),
+        }
+    }
+    result
+}
+
+/// Hepburn romaji of a run of kana, capitalized.
+fn romanize_run(run: &[char]) -> String {
+    let syllables = syllables(run);
+    let mut result = String::new();
+
+    for (i, syllable) in syllables.iter().enumerate() {
+        let next = syllables.get(i + 1).map(String::as_str).unwrap_or_default();
+        match syllable.as_str() {
+            // small tsu doubles the next consonant, `tch` before `ch`
+            "っ" => match next.chars().next() {
+                Some(_) if next.starts_with("ch") => result.push('t'),
+                Some(c) if c.is_ascii_alphabetic() && !is_vowel(c) && c != 'n' => result.push(c),
+                _ => (),
+            },
+            // long vowel mark repeats the vowel before it
+            "ー" => match result.chars().last() {
+                Some(c) if is_vowel(c) => result.push(c),
+                _ => result.push('-'),
+            },
+            "n" => {
+                result.push('n');
+                if next.starts_with(|c: char| is_vowel(c) || c == 'y') {
+                    result.push('\'');
+                }
+            }
+            s => result.push_str(s),
+        }
+    }
+
+    let mut chars = result.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => result,
+    }
+}
+
+/// `value` with kana transliterated to Hepburn romaji, each run of kana capitalized.
+/// kanji are kept as they are, as their reading is unknown.
+pub fn romanize(value: &str) -> String {
+    let chars: Vec<char> = value.nfkc().map(to_hiragana).collect();
+    let mut result = String::with_capacity(value.len());
+
+    let mut i = 0;
+    while i < chars.len() {
+        if is_kana(chars[i]) {
+            let start = i;
+            while i < chars.len() && is_kana(chars[i]) {
+                i += 1;
+            }
+            result.push_str(&romanize_run(&chars[start..i]));
+        } else {
+            match punctuation(chars[i]) {
+                Some(p) => result.push_str(p),
+                None => result.push(chars[i]),
+            }
+            i += 1;
+        }
+    }
+
+    result.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// romaji of `value` if it has kana and no kanji, None if it needs no sort name or the
+/// reading is unknown.
+pub fn title_sort(value: &str) -> Option<String> {
+    let value: String = value.nfkc().collect();
+    let has_kana = value.chars().map(to_hiragana).any(is_kana);
+    let has_kanji = value.chars().any(is_kanji);
+    (has_kana && !has_kanji).then(|| romanize(&value))
+}
+
+/// like [`title_sort`], and leading articles moved to the end for Latin names.
+pub fn artist_sort(value: &str) -> Option<String> {
+    if let Some(result) = title_sort(value) {
+        return Some(result);
+    }
+    ARTICLES.iter().find_map(|article| {
+        value
+            .strip_prefix(article)
+            .and_then(|rest| rest.strip_prefix(' '))
+            .filter(|rest| !rest.trim().is_empty())
+            .map(|rest| format!("{}, {}", rest.trim(), article))
+    })
+}
+
+/// true if `sort_name` can be used for sorting, i.e. it has no kanji.
+pub fn is_readable(sort_name: &str) -> bool {
+    !sort_name.chars().any(is_kanji)
+}
+
+/// generate sort names missing in `meta` from the titles and artists.
+pub fn fill(meta: &mut Metadata) {
+    if meta.album_sort.is_none() {
+        meta.album_sort = title_sort(&meta.album);
+    }
+    for track in &mut meta.tracks {
+        if track.title_sort.is_none() {
+            track.title_sort = title_sort(&track.title);
+        }
+        if track.artist_sort.is_none() {
+            track.artist_sort = artist_sort(&track.artist);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_kana_combine_with_previous() {
+        assert_eq!(romanize("きょう"), "Kyou");
+        assert_eq!(romanize("しゃしん"), "Shashin");
+        assert_eq!(romanize("ファイト"), "Faito");
+    }
+
+    #[test]
+    fn small_tsu_doubles_next_consonant() {
+        assert_eq!(romanize("がっこう"), "Gakkou");
+        assert_eq!(romanize("まっちゃ"), "Matcha");
+        assert_eq!(romanize("あっ"), "A");
+    }
+
+    #[test]
+    fn long_vowel_mark_repeats_vowel() {
+        assert_eq!(romanize("チョコレート"), "Chokoreeto");
+        assert_eq!(romanize("ー"), "-");
+    }
+
+    #[test]
+    fn syllabic_n_before_vowel_or_y() {
+        assert_eq!(romanize("こんや"), "Kon'ya");
+        assert_eq!(romanize("きんえん"), "Kin'en");
+        assert_eq!(romanize("さんぽ"), "Sanpo");
+    }
+
+    #[test]
+    fn title_sort_needs_kana_without_kanji() {
+        assert_eq!(title_sort("ｶﾀｶﾅ").as_deref(), Some("Katakana"));
+        assert_eq!(title_sort("ハレ ハレ").as_deref(), Some("Hare Hare"));
+        assert_eq!(title_sort("東京タワー"), None);
+        assert_eq!(title_sort("Hello"), None);
+    }
+
+    #[test]
+    fn artist_sort_moves_article() {
+        assert_eq!(artist_sort("The Beatles").as_deref(), Some("Beatles, The"));
+        assert_eq!(artist_sort("Theory"), None);
+        assert_eq!(artist_sort("The"), None);
+    }
+}
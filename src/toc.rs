@@ -0,0 +1,331 @@
+use std::{
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// sectors before the first track, which are not recorded in CUE sheets and logs.
+const PREGAP: u32 = 150;
+const SECTOR_BYTES: u64 = 2352;
+const SAMPLES_PER_SECTOR: u64 = 588;
+
+/// table of contents of an audio CD, in sectors (1/75 seconds) including the lead-in pregap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Toc {
+    pub first_track: u32,
+    pub leadout: u32,
+    pub offsets: Vec<u32>,
+}
+
+impl Toc {
+    pub fn new(offsets: Vec<u32>, leadout: u32) -> anyhow::Result<Toc> {
+        if offsets.is_empty() || offsets.len() > 99 {
+            anyhow::bail!("Error: a CD must have 1 to 99 tracks")
+        }
+        if offsets.windows(2).any(|w| w[0] >= w[1]) || leadout <= *offsets.last().unwrap() {
+            anyhow::bail!("Error: track offsets must be in ascending order and end before leadout")
+        }
+
+        Ok(Toc {
+            first_track: 1,
+            leadout,
+            offsets,
+        })
+    }
+
+    pub fn last_track(&self) -> u32 {
+        self.first_track + self.offsets.len() as u32 - 1
+    }
+
+    /// build toc from a CUE sheet.
+    /// if `leadout` is None, it is computed from the length of the WAV or FLAC files referenced by the sheet.
+    pub fn from_cue<P: AsRef<Path>>(cue: P, leadout: Option<u32>) -> anyhow::Result<Toc> {
+        let cue = cue.as_ref();
+        let dir = cue.parent().map(Path::to_path_buf).unwrap_or_default();
+        let text = std::fs::read_to_string(cue)?;
+        Toc::parse_cue(&text, &dir, leadout)
+    }
+
+    /// build toc from CUE sheet `text`, with files relative to `dir`.
+    fn parse_cue(text: &str, dir: &Path, leadout: Option<u32>) -> anyhow::Result<Toc> {
+        let mut files: Vec<PathBuf> = Vec::new();
+        // (file index, frames from the start of the file)
+        let mut indexes: Vec<(usize, u32)> = Vec::new();
+        let mut in_track = false;
+
+        for line in text.lines() {
+            let line = line.trim();
+            let (cmd, arg) = line.split_once(' ').unwrap_or((line, ""));
+
+            match cmd.to_uppercase().as_str() {
+                "FILE" => {
+                    let name = match arg.rsplit_once(' ') {
+                        Some((name, _)) => name.trim_matches('"'),
+                        None => arg.trim_matches('"'),
+                    };
+                    files.push(dir.join(name));
+                }
+                "TRACK" => in_track = arg.to_uppercase().ends_with("AUDIO"),
+                "INDEX" if in_track => {
+                    let (num, time) = arg.trim().split_once(' ').unwrap_or((arg, ""));
+                    if num == "01" {
+                        if files.is_empty() {
+                            anyhow::bail!("Error: INDEX appears before FILE in CUE sheet")
+                        }
+                        indexes.push((files.len() - 1, msf_to_frames(time.trim())?));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        // start sector of each file on the disc
+        let mut file_base = vec![0; files.len()];
+        for i in 1..files.len() {
+            file_base[i] = file_base[i - 1] + audio_sectors(&files[i - 1])?;
+        }
+
+        let offsets = indexes
+            .iter()
+            .map(|(file, frames)| file_base[*file] + frames + PREGAP)
+            .collect();
+
+        let leadout = match leadout {
+            Some(l) => l,
+            None if !files.is_empty() => {
+                file_base[files.len() - 1] + audio_sectors(&files[files.len() - 1])? + PREGAP
+            }
+            None => anyhow::bail!("Error: no FILE found in CUE sheet"),
+        };
+
+        Toc::new(offsets, leadout)
+    }
+
+    /// build toc from the TOC table of an EAC or XLD log.
+    pub fn from_log<P: AsRef<Path>>(log: P) -> anyhow::Result<Toc> {
+        let raw = std::fs::read(log)?;
+        Toc::parse_log(&decode_log(&raw))
+    }
+
+    fn parse_log(text: &str) -> anyhow::Result<Toc> {
+        let mut offsets = Vec::new();
+        let mut last_end = None;
+
+        for line in text.lines() {
+            let cols: Vec<_> = line.split('|').map(str::trim).collect();
+            if cols.len() != 5 || u32::from_str(cols[0]).is_err() {
+                continue;
+            }
+            let start = u32::from_str(cols[3])?;
+            let end = u32::from_str(cols[4])?;
+
+            // logs contain the TOC once per ripped disc, restart on track 1
+            if cols[0] == "1" {
+                offsets.clear();
+            }
+            offsets.push(start + PREGAP);
+            last_end = Some(end);
+        }
+
+        let last_end = last_end.ok_or(anyhow::anyhow!("Error: no TOC found in log"))?;
+        Toc::new(offsets, last_end + 1 + PREGAP)
+    }
+
+    /// toc in the format of the `toc` parameter of musicbrainz web service.
+    pub fn to_query(&self) -> String {
+        let mut result = format!(
+            "{} {} {}",
+            self.first_track,
+            self.last_track(),
+            self.leadout
+        );
+        for offset in &self.offsets {
+            result += &format!(" {}", offset);
+        }
+        result
+    }
+
+    /// compute musicbrainz disc ID.
+    /// see https://musicbrainz.org/doc/Disc_ID_Calculation
+    pub fn disc_id(&self) -> String {
+        let mut hash_src = format!(
+            "{:02X}{:02X}{:08X}",
+            self.first_track,
+            self.last_track(),
+            self.leadout
+        );
+        for i in 0..99 {
+            hash_src += &format!("{:08X}", self.offsets.get(i).copied().unwrap_or(0));
+        }
+
+        let digest = sha1_smol::Sha1::from(hash_src).digest().bytes();
+
+        base64::encode(digest)
+            .replace('+', ".")
+            .replace('/', "_")
+            .replace('=', "-")
+    }
+}
+
+pub(crate) fn msf_to_frames(msf: &str) -> anyhow::Result<u32> {
+    let parts: Vec<_> = msf.split(':').collect();
+    if parts.len() != 3 {
+        anyhow::bail!("Error: invalid time in CUE sheet: {}", msf)
+    }
+    let m = u32::from_str(parts[0])?;
+    let s = u32::from_str(parts[1])?;
+    let f = u32::from_str(parts[2])?;
+    Ok((m * 60 + s) * 75 + f)
+}
+
+/// EAC writes logs and CUE sheets in UTF-16LE with BOM.
+pub(crate) fn decode_log(raw: &[u8]) -> String {
+    if raw.starts_with(&[0xFF, 0xFE]) {
+        let utf16: Vec<u16> = raw[2..]
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        String::from_utf16_lossy(&utf16)
+    } else {
+        let raw = raw.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(raw);
+        String::from_utf8_lossy(raw).into_owned()
+    }
+}
+
+/// length of CD audio stored in WAV or FLAC file, in sectors.
+pub fn audio_sectors<P: AsRef<Path>>(path: P) -> anyhow::Result<u32> {
+    let path = path.as_ref();
+    let mut file = std::fs::File::open(path)
+        .map_err(|e| anyhow::anyhow!("Error: could not open {}: {}", path.display(), e))?;
+    let mut header = [0u8; 4];
+    file.read_exact(&mut header)?;
+
+    match &header {
+        b"fLaC" => {
+            // first metadata block is always STREAMINFO
+            let mut block = [0u8; 4 + 34];
+            file.read_exact(&mut block)?;
+            let info = &block[4..];
+            let total_samples = ((info[13] & 0x0F) as u64) << 32
+                | (info[14] as u64) << 24
+                | (info[15] as u64) << 16
+                | (info[16] as u64) << 8
+                | info[17] as u64;
+            Ok((total_samples / SAMPLES_PER_SECTOR) as u32)
+        }
+        b"RIFF" => {
+            // skip size and "WAVE", then walk chunks until "data"
+            file.seek(SeekFrom::Current(8))?;
+            let mut chunk = [0u8; 8];
+            while file.read_exact(&mut chunk).is_ok() {
+                let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as u64;
+                if &chunk[..4] == b"data" {
+                    return Ok((size / SECTOR_BYTES) as u32);
+                }
+                file.seek(SeekFrom::Current((size + (size & 1)) as i64))?;
+            }
+            anyhow::bail!("Error: no data chunk in {}", path.display())
+        }
+        _ => anyhow::bail!(
+            "Error: could not compute length of {}, only WAV and FLAC are supported",
+            path.display()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// example of https://musicbrainz.org/doc/Disc_ID_Calculation
+    fn example() -> Toc {
+        Toc::new(vec![150, 15363, 32314, 46592, 63414, 80489], 95462).unwrap()
+    }
+
+    #[test]
+    fn disc_id_of_known_toc() {
+        assert_eq!(example().disc_id(), "49HHV7Eb8UKF3aQiNmu1GR8vKTY-");
+    }
+
+    #[test]
+    fn query_lists_tracks_leadout_and_offsets() {
+        assert_eq!(
+            example().to_query(),
+            "1 6 95462 150 15363 32314 46592 63414 80489"
+        );
+    }
+
+    #[test]
+    fn new_rejects_unordered_offsets() {
+        assert!(Toc::new(vec![150, 100], 1000).is_err());
+        assert!(Toc::new(vec![150, 1000], 1000).is_err());
+        assert!(Toc::new(vec![], 1000).is_err());
+    }
+
+    #[test]
+    fn msf_is_converted_to_frames() {
+        assert_eq!(msf_to_frames("00:00:00").unwrap(), 0);
+        assert_eq!(msf_to_frames("03:24:74").unwrap(), (3 * 60 + 24) * 75 + 74);
+        assert!(msf_to_frames("03:24").is_err());
+    }
+
+    #[test]
+    fn cue_uses_index_01_of_audio_tracks() {
+        let cue = r#"REM GENRE Pop
+PERFORMER "Artist"
+FILE "image.wav" WAVE
+  TRACK 01 AUDIO
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    INDEX 00 03:20:00
+    INDEX 01 03:22:10
+  TRACK 03 AUDIO
+    INDEX 01 07:00:00
+"#;
+        let toc = Toc::parse_cue(cue, Path::new(""), Some(40000)).unwrap();
+        assert_eq!(toc.offsets, vec![150, 15160 + 150, 31500 + 150]);
+        assert_eq!(toc.leadout, 40000);
+    }
+
+    #[test]
+    fn cue_skips_data_tracks() {
+        let cue = "FILE \"a.bin\" BINARY\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\n\
+                   TRACK 02 MODE1/2352\n    INDEX 01 00:10:00\n";
+        let toc = Toc::parse_cue(cue, Path::new(""), Some(1000)).unwrap();
+        assert_eq!(toc.offsets, vec![150]);
+    }
+
+    #[test]
+    fn cue_without_file_is_rejected() {
+        let cue = "TRACK 01 AUDIO\n  INDEX 01 00:00:00\n";
+        assert!(Toc::parse_cue(cue, Path::new(""), Some(1000)).is_err());
+    }
+
+    #[test]
+    fn log_toc_restarts_on_each_disc() {
+        let log = "\
+     Track |   Start  |  Length  | Start sector | End sector
+    ---------------------------------------------------------
+        1  |  0:00.00 |  0:10.00 |         0    |       749
+        2  |  0:10.00 |  0:10.00 |       750    |      1499
+
+     Track |   Start  |  Length  | Start sector | End sector
+    ---------------------------------------------------------
+        1  |  0:00.00 |  3:24.62 |         0    |     15361
+        2  |  3:24.63 |  3:46.01 |     15362    |     32313
+";
+        let toc = Toc::parse_log(log).unwrap();
+        assert_eq!(toc.offsets, vec![150, 15512]);
+        assert_eq!(toc.leadout, 32314 + 150);
+    }
+
+    #[test]
+    fn log_in_utf16_is_decoded() {
+        let mut raw = vec![0xFF, 0xFE];
+        for unit in "TOC".encode_utf16() {
+            raw.extend(unit.to_le_bytes());
+        }
+        assert_eq!(decode_log(&raw), "TOC");
+        assert_eq!(decode_log(b"\xEF\xBB\xBFTOC"), "TOC");
+    }
+}
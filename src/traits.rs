@@ -17,6 +17,9 @@ pub trait FetchMeta {
     fn query(&self, query: &str) -> anyhow::Result<Vec<(Metadata, Vec<(String, String)>)>>;
     fn fetch_all(&self, id: &str) -> anyhow::Result<(Metadata, AddInfo)>;
 
+    /// find releases by barcode (EAN/UPC) or catalog number.
+    fn lookup(&self, code: &str) -> anyhow::Result<Vec<(Metadata, AddInfo)>>;
+
     fn fetch(&self, id: &str) -> anyhow::Result<Metadata> {
         self.fetch_all(id).map(|x| x.0)
     }